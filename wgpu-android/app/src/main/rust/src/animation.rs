@@ -9,11 +9,12 @@ pub mod jni_exports {
     use log::{debug, error, info, trace};
     use std::sync::{Arc, Mutex};
     use wgpu::{BackendOptions, Backends, Instance, InstanceDescriptor};
-    use wgpu_playground::WgpuStateInitInfo;
+    use wgpu_playground::{FrameStats, WgpuStateInitInfo};
 
     struct Wrapper {
         animator: Box<dyn Animate>,
         window: Arc<AndroidWindow>,
+        frame_stats: FrameStats,
     }
 
     fn create_init_info_from_window(android_window: Arc<AndroidWindow>) -> WgpuStateInitInfo {
@@ -30,6 +31,7 @@ pub mod jni_exports {
             instance,
             surface,
             size,
+            present: default!(),
         };
         init_info
     }
@@ -88,6 +90,7 @@ pub mod jni_exports {
                     let wrapper = Wrapper {
                         animator,
                         window: android_window,
+                        frame_stats: default!(),
                     };
                     Box::into_raw(Box::new(wrapper)) as jlong
                 };
@@ -164,6 +167,10 @@ pub mod jni_exports {
         trace!("update called");
         let wrapper = unsafe { &mut *(addr as *mut Wrapper) };
         wrapper.animator.frame().unwrap();
+        wrapper.frame_stats.tick();
+        if wrapper.frame_stats.report_due() {
+            info!("{}", wrapper.frame_stats);
+        }
     }
 
     #[unsafe(no_mangle)]
@@ -185,6 +192,7 @@ pub mod jni_exports {
         let wrapper = Wrapper {
             window: window_arc,
             animator,
+            frame_stats: default!(),
         };
         info!("changeAnimation end");
         Box::into_raw(Box::new(wrapper)) as jlong
@@ -15,7 +15,7 @@ pub struct RotatingTriangleAnimator {
 impl RotatingTriangleAnimator {
     pub fn new(init_info: WgpuStateInitInfo) -> anyhow::Result<Self> {
         Ok(Self {
-            state: pollster::block_on(triangle_rotation::State::new(init_info)),
+            state: pollster::block_on(triangle_rotation::State::new(init_info))?,
             elapsed: 0f32,
         })
     }
@@ -57,8 +57,9 @@ impl VsbmAnimator {
                 init_info,
                 vsbm::Config {
                     kernel_iterations: 2,
+                    ..default!()
                 },
-            )),
+            ))?,
         })
     }
 }
@@ -1,7 +1,10 @@
 //! A demonstration to https://github.com/niri-wm/niri/issues/3567.
 
+use clap::Parser;
 use std::env;
 use std::sync::Arc;
+use wgpu_playground::FrameStats;
+use wgpu_playground::surface::{PresentArgs, PresentConfig};
 use winit::application::ApplicationHandler;
 use winit::event::{ElementState, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
@@ -10,20 +13,30 @@ use winit::window::{Window, WindowAttributes, WindowId};
 
 const STRIPE_WIDTH: f32 = 100f32;
 
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    present: PresentArgs,
+}
+
 struct App {
     state: Option<render::State>,
     window: Option<Arc<Window>>,
     delta: f32,
     paused: bool,
+    frame_stats: FrameStats,
+    present: PresentConfig,
 }
 
 impl App {
-    fn new() -> Self {
+    fn new(present: PresentConfig) -> Self {
         Self {
             window: None,
             state: None,
             delta: 8.,
             paused: false,
+            frame_stats: FrameStats::default(),
+            present,
         }
     }
 }
@@ -35,7 +48,11 @@ impl ApplicationHandler for App {
 
         pollster::block_on(async {
             let _a = event_loop.owned_display_handle();
-            self.state = Some(render::State::new(Arc::clone(&window)).await.unwrap());
+            self.state = Some(
+                render::State::new(Arc::clone(&window), self.present)
+                    .await
+                    .unwrap(),
+            );
         });
 
         window.request_redraw();
@@ -53,7 +70,7 @@ impl ApplicationHandler for App {
             WindowEvent::RedrawRequested => {
                 if let Some(w) = &self.window {
                     state.left += self.delta;
-                    if state.left >= state.size.0 as f32 - STRIPE_WIDTH {
+                    if state.left >= state.size().0 as f32 - STRIPE_WIDTH {
                         self.delta = -self.delta;
                     }
                     if state.left <= 0.0 {
@@ -64,6 +81,10 @@ impl ApplicationHandler for App {
                         state.render(|| {
                             // w.pre_present_notify();
                         });
+                        self.frame_stats.tick();
+                        if self.frame_stats.report_due() {
+                            println!("{}", self.frame_stats);
+                        }
                         w.request_redraw();
                     }
                 }
@@ -81,6 +102,7 @@ impl ApplicationHandler for App {
                 }
                 if event.logical_key == Key::Named(NamedKey::Space) {
                     self.paused = !self.paused;
+                    self.frame_stats.switch_pause();
                     println!("Animation paused: {}", self.paused);
                     if !self.paused {
                         window.request_redraw();
@@ -99,32 +121,31 @@ mod render {
     use std::sync::Arc;
     use wgpu::util::RenderEncoder;
     use wgpu::{
-        include_wgsl, Buffer, BufferDescriptor, BufferUsages, Color, ColorTargetState,
-        CurrentSurfaceTexture, Features, FragmentState, IndexFormat, PipelineLayoutDescriptor,
-        RenderPipeline, RenderPipelineDescriptor, VertexState,
+        Buffer, BufferDescriptor, BufferUsages, Color, ColorTargetState, Features, FragmentState,
+        IndexFormat, PipelineLayoutDescriptor, RenderPipeline, RenderPipelineDescriptor,
+        VertexState, include_wgsl,
     };
-    use wgpu_playground::{wgpu_instance_with_env_backend, ColorExt};
+    use wgpu_playground::surface::{PresentConfig, SrgbPolicy, SurfaceManager};
+    use wgpu_playground::{AdapterSelector, ColorExt, wgpu_instance_with_env_backend};
     use winit::window::Window;
 
     pub struct State {
         device: wgpu::Device,
         queue: wgpu::Queue,
-        pub size: (u32, u32),
-        surface: wgpu::Surface<'static>,
-        surface_format: wgpu::TextureFormat,
+        surface: SurfaceManager,
         pipeline: RenderPipeline,
         ibo: Buffer,
         pub left: f32,
     }
 
     impl State {
-        pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+        pub async fn new(window: Arc<Window>, present: PresentConfig) -> anyhow::Result<Self> {
             let instance = wgpu_instance_with_env_backend();
             let size = window.inner_size();
             let surface = instance.create_surface(Arc::clone(&window))?;
 
-            let adapter = instance
-                .request_adapter(&wgpu::RequestAdapterOptions::default())
+            let adapter = AdapterSelector::from_env()
+                .select(&instance, Some(&surface))
                 .await?;
             let (device, queue) = adapter
                 .request_device(&{
@@ -135,9 +156,14 @@ mod render {
                 })
                 .await?;
 
-            let cap = surface.get_capabilities(&adapter);
-
-            let surface_format = cap.formats[0].remove_srgb_suffix();
+            let mut surface = SurfaceManager::new(
+                surface,
+                &adapter,
+                &device,
+                (size.width, size.height),
+                SrgbPolicy::NoSrgb,
+            );
+            surface.set_present_config(present)?;
 
             let shader_module =
                 device.create_shader_module(include_wgsl!("../shaders/burst-animation.wgsl"));
@@ -160,7 +186,7 @@ mod render {
                     entry_point: None,
                     compilation_options: Default::default(),
                     targets: &[Some(ColorTargetState {
-                        format: surface_format,
+                        format: surface.format(),
                         blend: None,
                         write_mask: Default::default(),
                     })],
@@ -183,59 +209,28 @@ mod render {
             });
             queue.write_buffer(&ibo, 0, bytes_of(&indices));
 
-            let state = State {
+            Ok(State {
                 device,
                 queue,
-                size: (size.width, size.height),
                 surface,
-                surface_format,
                 pipeline,
                 ibo,
                 left: 0.0,
-            };
-
-            // Configure surface for the first time
-            state.configure_surface();
-
-            Ok(state)
+            })
         }
 
-        pub fn configure_surface(&self) {
-            let surface_config = wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format: self.surface_format,
-                view_formats: vec![self.surface_format],
-                alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                width: self.size.0,
-                height: self.size.1,
-                desired_maximum_frame_latency: 2,
-                present_mode: wgpu::PresentMode::AutoVsync,
-            };
-            self.surface.configure(&self.device, &surface_config);
+        pub fn size(&self) -> (u32, u32) {
+            self.surface.size()
         }
 
         pub fn resize(&mut self, new_size: (u32, u32)) {
-            self.size = new_size;
-
-            // reconfigure the surface
-            self.configure_surface();
+            self.surface.resize(new_size);
         }
 
         pub fn render(&self, redraw_callback: impl FnOnce()) {
-            // Create texture view
-            let surface_texture = match self.surface.get_current_texture() {
-                CurrentSurfaceTexture::Success(t) => t,
-                _ => {
-                    self.configure_surface();
-                    return;
-                }
+            let Some(frame) = self.surface.acquire() else {
+                return;
             };
-            let texture_view = surface_texture
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    format: Some(self.surface_format),
-                    ..Default::default()
-                });
 
             // Renders a gray screen
             let mut encoder = self.device.create_command_encoder(&Default::default());
@@ -243,7 +238,7 @@ mod render {
             let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
+                    view: &frame.view,
                     depth_slice: None,
                     resolve_target: None,
                     ops: wgpu::Operations {
@@ -266,12 +261,14 @@ mod render {
 
             redraw_callback();
             self.queue.submit([encoder.finish()]);
-            surface_texture.present();
+            frame.present();
         }
     }
 }
 
 fn main() {
+    let args = Args::parse();
+
     unsafe {
         env::set_var("RUST_LOG", "info");
     }
@@ -281,6 +278,6 @@ fn main() {
 
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = App::new();
+    let mut app = App::new((&args.present).into());
     event_loop.run_app(&mut app).unwrap();
 }
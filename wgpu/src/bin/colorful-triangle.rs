@@ -84,23 +84,22 @@ fn main() {
 mod render {
     use bytemuck::bytes_of;
     use chrono::{Local, Timelike};
-    use log::{error, info};
+    use log::info;
     use palette::{FromColor, Srgb};
     use std::sync::Arc;
     use wgpu::{
-        BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer,
-        BufferDescriptor, BufferUsages, Color, ColorTargetState, CurrentSurfaceTexture,
-        FragmentState, RenderPipeline, RenderPipelineDescriptor, VertexState, include_wgsl,
+        BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferDescriptor, BufferUsages,
+        Color, ColorTargetState, FragmentState, RenderPipeline, RenderPipelineDescriptor,
+        VertexState, include_wgsl,
     };
-    use wgpu_playground::{ColorExt, wgpu_instance_with_env_backend};
+    use wgpu_playground::surface::{SrgbPolicy, SurfaceManager};
+    use wgpu_playground::{AdapterSelector, ColorExt, wgpu_instance_with_env_backend};
     use winit::window::Window;
 
     pub struct State {
         device: wgpu::Device,
         queue: wgpu::Queue,
-        size: (u32, u32),
-        surface: wgpu::Surface<'static>,
-        surface_format: wgpu::TextureFormat,
+        surface: SurfaceManager,
         pipeline: RenderPipeline,
         uniform: Buffer,
         bind_group: BindGroup,
@@ -114,16 +113,20 @@ mod render {
                 .create_surface(Arc::clone(&window))
                 .map_err(anyhow::Error::msg)?;
 
-            let adapter = instance
-                .request_adapter(&wgpu::RequestAdapterOptions::default())
+            let adapter = AdapterSelector::from_env()
+                .select(&instance, Some(&surface))
                 .await?;
             let (device, queue) = adapter
                 .request_device(&wgpu::DeviceDescriptor::default())
                 .await?;
 
-            let cap = surface.get_capabilities(&adapter);
-
-            let surface_format = cap.formats[0].remove_srgb_suffix();
+            let surface = SurfaceManager::new(
+                surface,
+                &adapter,
+                &device,
+                (size.width, size.height),
+                SrgbPolicy::NoSrgb,
+            );
 
             let shader_module =
                 device.create_shader_module(include_wgsl!("../shaders/colorful-triangle.wgsl"));
@@ -140,7 +143,7 @@ mod render {
                     entry_point: None,
                     compilation_options: Default::default(),
                     targets: &[Some(ColorTargetState {
-                        format: surface_format,
+                        format: surface.format(),
                         blend: None,
                         write_mask: Default::default(),
                     })],
@@ -172,22 +175,13 @@ mod render {
             let state = State {
                 device,
                 queue,
-                size: (size.width, size.height),
                 surface,
-                surface_format,
                 pipeline,
                 uniform,
                 bind_group,
             };
             state.update_uniform(bytes_of(&[1.0_f32, 0.0, 0.0, 1.0]));
 
-            // Configure surface for the first time
-            info!(
-                "Initial surface configuration; size: {:?}",
-                (size.width, size.height)
-            );
-            state.configure_surface();
-
             Ok(state)
         }
 
@@ -195,56 +189,16 @@ mod render {
             self.queue.write_buffer(&self.uniform, 0, data);
         }
 
-        pub fn configure_surface(&self) {
-            let surface_config = wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format: self.surface_format,
-                view_formats: vec![self.surface_format],
-                alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                width: self.size.0,
-                height: self.size.1,
-                desired_maximum_frame_latency: 2,
-                present_mode: wgpu::PresentMode::AutoVsync,
-            };
-            self.surface.configure(&self.device, &surface_config);
-        }
-
         pub fn resize(&mut self, new_size: (u32, u32)) {
-            self.size = new_size;
-
-            // reconfigure the surface
-            info!("Resize and reconfigure; size: {:?}", self.size);
-            self.configure_surface();
+            self.surface.resize(new_size);
         }
 
         pub fn render(&self, pre_present_callback: impl FnOnce()) {
-            info!("Render; size: {:?}", self.size);
+            info!("Render; size: {:?}", self.surface.size());
 
-            // Create texture view
-            let surface_texture = match self.surface.get_current_texture() {
-                CurrentSurfaceTexture::Success(texture) => texture,
-                CurrentSurfaceTexture::Suboptimal(texture) => {
-                    self.configure_surface();
-                    texture
-                }
-                CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
-                    return;
-                }
-                CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
-                    self.configure_surface();
-                    return;
-                }
-                CurrentSurfaceTexture::Validation => {
-                    error!("Validation error in get_current_texture");
-                    return;
-                }
+            let Some(frame) = self.surface.acquire() else {
+                return;
             };
-            let texture_view = surface_texture
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    format: Some(self.surface_format),
-                    ..Default::default()
-                });
 
             // Renders a gray screen
             let mut encoder = self.device.create_command_encoder(&Default::default());
@@ -252,7 +206,7 @@ mod render {
             let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
+                    view: &frame.view,
                     depth_slice: None,
                     resolve_target: None,
                     ops: wgpu::Operations {
@@ -282,7 +236,7 @@ mod render {
 
             self.queue.submit([encoder.finish()]);
             pre_present_callback();
-            surface_texture.present();
+            frame.present();
         }
     }
 }
@@ -11,7 +11,7 @@ use wgpu::{
     BufferDescriptor, BufferUsages, ComputePipeline, ComputePipelineDescriptor, Device,
     MapMode, PipelineCompilationOptions, Queue, include_wgsl,
 };
-use wgpu_playground::{set_up_logger, wgpu_instance_with_env_backend};
+use wgpu_playground::{AdapterSelector, set_up_logger, wgpu_instance_with_env_backend};
 
 macro default() {
     Default::default()
@@ -31,7 +31,7 @@ struct State {
 impl State {
     async fn new(work_buffer_size: u64) -> anyhow::Result<Self> {
         let instance = wgpu_instance_with_env_backend();
-        let adapter = instance.request_adapter(&default!()).await?;
+        let adapter = AdapterSelector::from_env().select(&instance, None).await?;
         let (device, queue) = adapter.request_device(&default!()).await?;
 
         let shader_module =
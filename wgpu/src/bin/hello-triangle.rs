@@ -76,23 +76,21 @@ fn main() {
 }
 
 mod render {
-    
+
     use chrono::Local;
-    use log::error;
     use std::sync::Arc;
     use wgpu::{
-        Color, ColorTargetState, CurrentSurfaceTexture,
-        FragmentState, RenderPipeline, RenderPipelineDescriptor, VertexState, include_wgsl,
+        Color, ColorTargetState, FragmentState, RenderPipeline, RenderPipelineDescriptor,
+        VertexState, include_wgsl,
     };
-    use wgpu_playground::{ColorExt, wgpu_instance_with_env_backend};
+    use wgpu_playground::surface::{SrgbPolicy, SurfaceManager};
+    use wgpu_playground::{AdapterSelector, ColorExt, wgpu_instance_with_env_backend};
     use winit::window::Window;
 
     pub struct State {
         device: wgpu::Device,
         queue: wgpu::Queue,
-        size: (u32, u32),
-        surface: wgpu::Surface<'static>,
-        surface_format: wgpu::TextureFormat,
+        surface: SurfaceManager,
         pipeline: RenderPipeline,
     }
 
@@ -104,16 +102,20 @@ mod render {
                 .create_surface(Arc::clone(&window))
                 .map_err(anyhow::Error::msg)?;
 
-            let adapter = instance
-                .request_adapter(&wgpu::RequestAdapterOptions::default())
+            let adapter = AdapterSelector::from_env()
+                .select(&instance, Some(&surface))
                 .await?;
             let (device, queue) = adapter
                 .request_device(&wgpu::DeviceDescriptor::default())
                 .await?;
 
-            let cap = surface.get_capabilities(&adapter);
-
-            let surface_format = cap.formats[0].remove_srgb_suffix();
+            let surface = SurfaceManager::new(
+                surface,
+                &adapter,
+                &device,
+                (size.width, size.height),
+                SrgbPolicy::NoSrgb,
+            );
 
             let shader_module =
                 device.create_shader_module(include_wgsl!("../shaders/hello-triangle.wgsl"));
@@ -130,7 +132,7 @@ mod render {
                     entry_point: None,
                     compilation_options: Default::default(),
                     targets: &[Some(ColorTargetState {
-                        format: surface_format,
+                        format: surface.format(),
                         blend: None,
                         write_mask: Default::default(),
                     })],
@@ -144,68 +146,22 @@ mod render {
                 cache: None,
             });
 
-            let state = State {
+            Ok(State {
                 device,
                 queue,
-                size: (size.width, size.height),
                 surface,
-                surface_format,
                 pipeline,
-            };
-
-            // Configure surface for the first time
-            state.configure_surface();
-
-            Ok(state)
-        }
-
-        pub fn configure_surface(&self) {
-            let surface_config = wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format: self.surface_format,
-                view_formats: vec![self.surface_format],
-                alpha_mode: wgpu::CompositeAlphaMode::Auto,
-                width: self.size.0,
-                height: self.size.1,
-                desired_maximum_frame_latency: 2,
-                present_mode: wgpu::PresentMode::AutoVsync,
-            };
-            self.surface.configure(&self.device, &surface_config);
+            })
         }
 
         pub fn resize(&mut self, new_size: (u32, u32)) {
-            self.size = new_size;
-
-            // reconfigure the surface
-            self.configure_surface();
+            self.surface.resize(new_size);
         }
 
         pub fn render(&self, redraw_callback: impl FnOnce()) {
-            // Create texture view
-            let surface_texture = match self.surface.get_current_texture() {
-                CurrentSurfaceTexture::Success(texture) => texture,
-                CurrentSurfaceTexture::Suboptimal(texture) => {
-                    self.configure_surface();
-                    texture
-                }
-                CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
-                    return;
-                }
-                CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
-                    self.configure_surface();
-                    return;
-                }
-                CurrentSurfaceTexture::Validation => {
-                    error!("Validation error in get_current_texture");
-                    return;
-                }
+            let Some(frame) = self.surface.acquire() else {
+                return;
             };
-            let texture_view = surface_texture
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    format: Some(self.surface_format),
-                    ..Default::default()
-                });
 
             // Renders a gray screen
             let mut encoder = self.device.create_command_encoder(&Default::default());
@@ -213,7 +169,7 @@ mod render {
             let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
+                    view: &frame.view,
                     depth_slice: None,
                     resolve_target: None,
                     ops: wgpu::Operations {
@@ -235,7 +191,7 @@ mod render {
 
             redraw_callback();
             self.queue.submit([encoder.finish()]);
-            surface_texture.present();
+            frame.present();
         }
     }
 }
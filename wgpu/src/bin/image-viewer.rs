@@ -1,38 +1,73 @@
+//! Keys: `←`/`→` or the mouse wheel switch images, `Ctrl`+wheel (or pinch) zooms at the cursor,
+//! dragging pans, `0`/`1`/`2` select fit/1:1/fill, `s` toggles scaling, `r` toggles keeping
+//! the aspect ratio, `b` toggles bicubic magnification, `f` toggles fullscreen and `q` quits.
+//! For HDR images `+`/`-` change the exposure and `t` toggles tone mapping. Animated GIF, APNG
+//! and WebP images loop; `Space` pauses and `,`/`.` step one frame back/forward.
+//!
+//! Adjustments: `a` selects brightness, contrast, gamma or saturation and `↑`/`↓` change it,
+//! `i` inverts, `c` cycles through the RGB, single channel and luminance views and `x` resets
+//! them. The info window shows the value of the pixel under the cursor.
+//!
+//! Editing: `[`/`]` rotate by 90°, `h`/`v` flip and `Ctrl`+`s` saves the rotated and adjusted
+//! image as a PNG next to the original (`<name>-edited.png`; existing files are kept).
+//!
+//! Files are recognized by their content, so any format the `image` crate decodes is shown.
+//! Files that fail to decode show an error card instead. Photos are shown upright according
+//! to their EXIF orientation, and the info window lists their size, format and key EXIF tags.
+
+use anyhow::bail;
 use bytemuck::{Pod, Zeroable, bytes_of};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use cosmic_text::Family;
-use image::GenericImageView;
-use log::{error, info};
+use exif::{In, Tag};
+use half::f16;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::metadata::Orientation;
+use image::{AnimationDecoder, ColorType, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use log::{error, info, warn};
 use static_assertions::assert_eq_size;
-use std::ffi::OsStr;
-use std::io::Read;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::slice::SliceIndex;
 use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
 use std::{fmt, fs};
 use wgpu::{
     AddressMode, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferDescriptor,
     BufferUsages, ColorTargetState, Device, Extent3d, FilterMode, FragmentState, Instance, LoadOp,
-    LoadOpDontCare, Operations, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, SamplerDescriptor, StoreOp, Surface, SurfaceConfiguration,
-    TexelCopyBufferLayout, TexelCopyTextureInfo, TextureDescriptor, TextureDimension,
-    TextureFormat, TextureUsages, TextureViewDescriptor, TextureViewDimension, VertexState,
-    include_wgsl,
+    LoadOpDontCare, MipmapFilterMode, Operations, Origin3d, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor,
+    ShaderModule, StoreOp, Surface, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor, TextureViewDimension,
+    VertexState, include_wgsl,
+};
+use wgpu_playground::surface::{PresentArgs, PresentConfig, SrgbPolicy, SurfaceManager};
+use wgpu_playground::{
+    AdapterSelector, default, read_texture, set_up_logger, wgpu_instance_with_env_backend,
 };
-use wgpu_playground::{default, set_up_logger, wgpu_instance_with_env_backend};
 use winit::application::ApplicationHandler;
 use winit::dpi::LogicalSize;
-use winit::event::{MouseScrollDelta, WindowEvent};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::keyboard::{Key, NamedKey};
+use winit::keyboard::{Key, ModifiersState, NamedKey};
 use winit::window::{Fullscreen, Window, WindowAttributes, WindowId};
 
 struct InfoState {
     surface: softbuffer::Surface<Arc<Window>, Arc<Window>>,
     size: (u32, u32),
     text_renderer: TextRenderer,
+    /// Shown above `text`; changes with the view
+    status: String,
     text: String,
+    /// Metadata of the shown image; see [`DecodedImage::metadata`]
+    metadata: String,
 }
 
 impl InfoState {
@@ -44,7 +79,9 @@ impl InfoState {
             surface,
             size: (0, 0),
             text_renderer: TextRenderer::new(),
+            status: String::default(),
             text: String::default(),
+            metadata: String::default(),
         }
     }
 
@@ -61,8 +98,8 @@ impl InfoState {
         }
         let mut buffer = self.surface.buffer_mut().unwrap();
         buffer.fill(0x000000);
-        self.text_renderer
-            .render(buffer.as_mut(), self.size, &self.text);
+        let text = format!("{}\n{}\n{}", self.status, self.text, self.metadata);
+        self.text_renderer.render(buffer.as_mut(), self.size, &text);
         buffer.present().unwrap();
     }
 }
@@ -75,6 +112,10 @@ struct App<'a> {
     image_list: Vec<PathBuf>,
     image_index: usize,
     args: &'a Args,
+    modifiers: ModifiersState,
+    dragging: bool,
+    cursor_position: (f64, f64),
+    images: ImageCache,
 }
 
 impl ApplicationHandler for App<'_> {
@@ -90,16 +131,14 @@ impl ApplicationHandler for App<'_> {
         let surface = instance.create_surface(window.clone()).unwrap();
         let window_size = window.inner_size();
 
-        let mut state = State::new(
+        let state = State::new(
             instance,
             surface,
             (window_size.width, window_size.height),
             self.args.no_scale,
+            (&self.args.present).into(),
         )
         .unwrap();
-        state
-            .update_image(&self.image_list[self.image_index])
-            .unwrap();
 
         self.state = Some(state);
         self.window = Some(Arc::clone(&window));
@@ -116,7 +155,18 @@ impl ApplicationHandler for App<'_> {
         self.info_state = Some(info_state);
         self.info_window = Some(info_window);
 
-        self.update_info_text();
+        self.show_current_image();
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        for (index, result) in self.images.poll() {
+            if index != self.image_index {
+                continue;
+            }
+            if let Err(e) = result.and_then(|image| self.display_image(&image)) {
+                self.show_error(e);
+            }
+        }
     }
 
     fn window_event(
@@ -166,6 +216,9 @@ impl ApplicationHandler for App<'_> {
                         Key::Named(NamedKey::ArrowRight) => {
                             self.next_image();
                         }
+                        Key::Character(x) if x == "s" && self.modifiers.control_key() => {
+                            self.save_view();
+                        }
                         Key::Character(x) if x == "s" => {
                             state.uniform_data.no_scale.flip();
                             state.configure_bind_group();
@@ -174,9 +227,71 @@ impl ApplicationHandler for App<'_> {
                             state.uniform_data.proportional.flip();
                             state.configure_bind_group();
                         }
+                        Key::Character(x) if x == "+" || x == "=" => {
+                            state.uniform_data.exposure += 0.5;
+                        }
+                        Key::Character(x) if x == "-" => {
+                            state.uniform_data.exposure -= 0.5;
+                        }
+                        Key::Character(x) if x == "t" => {
+                            state.uniform_data.tone_map.flip();
+                        }
+                        Key::Character(x) if x == "b" => {
+                            state.uniform_data.bicubic.flip();
+                        }
+                        Key::Character(x) if x == "a" => {
+                            state.adjustment = state.adjustment.next();
+                        }
+                        Key::Named(NamedKey::ArrowUp) => state.adjust(1.0),
+                        Key::Named(NamedKey::ArrowDown) => state.adjust(-1.0),
+                        Key::Character(x) if x == "i" => {
+                            state.uniform_data.invert.flip();
+                        }
+                        Key::Character(x) if x == "c" => {
+                            let channel = ChannelView::from_code(state.uniform_data.channel);
+                            state.uniform_data.channel = channel.next() as u32;
+                        }
+                        Key::Character(x) if x == "x" => state.uniform_data.reset_adjustments(),
+                        Key::Character(x) if x == "[" => state.rotate(false),
+                        Key::Character(x) if x == "]" => state.rotate(true),
+                        Key::Character(x) if x == "h" => state.flip(false),
+                        Key::Character(x) if x == "v" => state.flip(true),
+                        Key::Named(NamedKey::Space) => state.toggle_pause(),
+                        Key::Character(x) if x == "," => state.step_frame(false),
+                        Key::Character(x) if x == "." => state.step_frame(true),
+                        Key::Character(x) if x == "0" => state.set_view(ViewPreset::Fit),
+                        Key::Character(x) if x == "1" => state.set_view(ViewPreset::Actual),
+                        Key::Character(x) if x == "2" => state.set_view(ViewPreset::Fill),
                         _ => {}
                     }
                 }
+                WindowEvent::ModifiersChanged(modifiers) => {
+                    self.modifiers = modifiers.state();
+                }
+                WindowEvent::MouseInput {
+                    state: e_state,
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    self.dragging = *e_state == ElementState::Pressed;
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    if self.dragging {
+                        let (x, y) = self.cursor_position;
+                        state.pan((position.x - x, position.y - y));
+                    }
+                    self.cursor_position = (position.x, position.y);
+                }
+                WindowEvent::MouseWheel { delta, .. } if self.modifiers.control_key() => {
+                    let y = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+                    };
+                    state.zoom_at(self.cursor_position, 1.25_f32.powf(y));
+                }
+                WindowEvent::PinchGesture { delta, .. } => {
+                    state.zoom_at(self.cursor_position, 1.0 + *delta as f32);
+                }
                 WindowEvent::MouseWheel {
                     delta: MouseScrollDelta::LineDelta(_x, y),
                     ..
@@ -202,6 +317,15 @@ impl ApplicationHandler for App<'_> {
             }
         }
 
+        if window_id == main_window_id
+            && let (Some(state), Some(info_state)) = (&self.state, &mut self.info_state)
+        {
+            info_state.status = state.status_text();
+            if let Some(probe) = state.probe(self.cursor_position) {
+                info_state.status += &format!("\n{}", probe);
+            }
+        }
+
         // common
         if event == WindowEvent::CloseRequested {
             event_loop.exit();
@@ -316,34 +440,93 @@ impl App<'_> {
         }
     }
 
-    fn previous_image(&mut self) {
-        let Some(ref mut state) = self.state else {
+    /// Shows the image at `image_index` if it's decoded already, otherwise once it is, and
+    /// prefetches its neighbours.
+    fn show_current_image(&mut self) {
+        info!("Set image: {}", self.image_list[self.image_index].display());
+        self.images
+            .prefetch(&self.image_list, self.image_index, self.args.prefetch);
+        match self.images.get(self.image_index) {
+            Some(image) => {
+                if let Err(e) = self.display_image(&image) {
+                    self.show_error(e);
+                }
+            }
+            None => {
+                if let Some(info_state) = &mut self.info_state {
+                    // not decoded yet
+                    info_state.metadata.clear();
+                }
+            }
+        }
+        self.update_info_text();
+    }
+
+    /// Uploads `image` and shows its metadata.
+    fn display_image(&mut self, image: &Arc<DecodedImage>) -> anyhow::Result<()> {
+        if let Some(state) = &mut self.state {
+            state.set_image(image)?;
+        }
+        if let Some(info_state) = &mut self.info_state {
+            info_state.metadata = image.metadata.clone();
+        }
+        Ok(())
+    }
+
+    /// Shows a card with `error` in place of the current image.
+    fn show_error(&mut self, error: anyhow::Error) {
+        let path = &self.image_list[self.image_index];
+        error!("{}: {}", path.display(), error);
+        let (Some(state), Some(info_state)) = (&mut self.state, &mut self.info_state) else {
+            return;
+        };
+        let message = format!("Can't open {}\n\n{:#}", path.display(), error);
+        let card = Arc::new(error_card(&mut info_state.text_renderer, &message));
+        info_state.metadata.clear();
+        if let Err(e) = state.set_image(&card) {
+            error!("Failed to show the error card: {}", e);
+        }
+    }
+
+    /// Saves the current image as shown (orientation and adjustments, not zoom) next to it.
+    fn save_view(&mut self) {
+        let (Some(state), Some(info_state)) = (&self.state, &mut self.info_state) else {
             return;
         };
+        let result = (|| -> anyhow::Result<PathBuf> {
+            let path = edited_path(&self.image_list[self.image_index]);
+            state.render_to_image()?.save(&path)?;
+            Ok(path)
+        })();
+        use fmt::Write;
+        match result {
+            Ok(path) => {
+                info!("Saved {}", path.display());
+                writeln!(&mut info_state.text, "\nSaved to {}", path.display()).unwrap();
+            }
+            Err(e) => {
+                error!("Failed to save: {}", e);
+                writeln!(&mut info_state.text, "\nFailed to save: {}", e).unwrap();
+            }
+        }
+    }
+
+    fn previous_image(&mut self) {
         if self.image_index == 0 {
             self.image_index = self.image_list.len() - 1;
         } else {
             self.image_index -= 1;
         }
-        state
-            .update_image(&self.image_list[self.image_index])
-            .unwrap();
-        self.update_info_text();
+        self.show_current_image();
     }
 
     fn next_image(&mut self) {
-        let Some(ref mut state) = self.state else {
-            return;
-        };
         if self.image_index == self.image_list.len() - 1 {
             self.image_index = 0;
         } else {
             self.image_index += 1;
         }
-        state
-            .update_image(&self.image_list[self.image_index])
-            .unwrap();
-        self.update_info_text();
+        self.show_current_image();
     }
 
     fn toggle_fullscreen(window: &Window) {
@@ -360,39 +543,417 @@ impl App<'_> {
     }
 }
 
-/// Returns rgba8888
-fn open_image(path: impl AsRef<Path>) -> anyhow::Result<(u32, u32, Vec<u8>)> {
-    let img = image::open(path)?;
-    let width = img.width();
-    let height = img.height();
+/// `<stem>-edited.png` next to `path`, numbered so that no existing file is overwritten.
+fn edited_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut candidate = path.with_file_name(format!("{}-edited.png", stem));
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = path.with_file_name(format!("{}-edited-{}.png", stem, n));
+        n += 1;
+    }
+    candidate
+}
+
+/// Renders `message` as an image, for showing in place of one that failed to open.
+fn error_card(text_renderer: &mut TextRenderer, message: &str) -> DecodedImage {
+    const SIZE: (u32, u32) = (960, 540);
+    let mut pixels = vec![0_u32; SIZE.0 as usize * SIZE.1 as usize];
+    text_renderer.render(&mut pixels, SIZE, message);
+    DecodedImage {
+        width: SIZE.0,
+        height: SIZE.1,
+        format: TextureFormat::Rgba8Unorm,
+        linear: false,
+        orientation: Orientation::NoTransforms,
+        metadata: String::new(),
+        frames: vec![Frame {
+            data: pixels
+                .into_iter()
+                .flat_map(|x| {
+                    let [_, r, g, b] = x.to_be_bytes();
+                    [r, g, b, 0xFF]
+                })
+                .collect(),
+            delay: Duration::ZERO,
+        }],
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum SortOrder {
+    /// By path
+    Name,
+    /// By path, with digit runs compared as numbers (`2.png` before `10.png`)
+    Natural,
+    /// By modification time, oldest first
+    Mtime,
+    /// By file size, smallest first
+    Size,
+}
+
+/// Whether `path` looks like an image we can decode, judging by its content and falling back
+/// to the extension.
+fn is_image(path: &Path) -> bool {
+    let Ok(reader) = ImageReader::open(path).and_then(|x| x.with_guessed_format()) else {
+        return false;
+    };
+    reader.format().is_some_and(|x| x.reading_enabled())
+}
+
+/// Collects the images in `dir`, descending into subfolders if `recursive`.
+fn list_images(dir: &Path, recursive: bool, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive && let Err(e) = list_images(&path, recursive, out) {
+                warn!("Skipping {}: {}", path.display(), e);
+            }
+        } else if is_image(&path) {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compares strings treating runs of ASCII digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (a_num, b_num) = (
+                a[..a_end].trim_start_matches('0'),
+                b[..b_end].trim_start_matches('0'),
+            );
+            // compare by length first, so arbitrarily long numbers don't overflow
+            let ordering = a_num
+                .len()
+                .cmp(&b_num.len())
+                .then_with(|| a_num.cmp(b_num))
+                .then_with(|| a_end.cmp(&b_end));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (&a[a_end..], &b[b_end..]);
+        } else {
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            (a, b) = (&a[ca.len_utf8()..], &b[cb.len_utf8()..]);
+        }
+    }
+}
+
+fn sort_images(list: &mut [PathBuf], order: SortOrder) {
+    match order {
+        SortOrder::Name => list.sort(),
+        SortOrder::Natural => {
+            list.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()))
+        }
+        SortOrder::Mtime => list.sort_by_cached_key(|x| {
+            let modified = fs::metadata(x).and_then(|x| x.modified()).ok();
+            (modified, x.clone())
+        }),
+        SortOrder::Size => list.sort_by_cached_key(|x| {
+            let size = fs::metadata(x).map(|x| x.len()).unwrap_or_default();
+            (size, x.clone())
+        }),
+    }
+}
+
+/// A decoded image, ready to be uploaded
+struct DecodedImage {
+    width: u32,
+    height: u32,
+    /// `Rgba8Unorm`, or `Rgba16Float` for images with more than 8 bits per channel
+    format: TextureFormat,
+    /// Whether the values are linear light (EXR, HDR) rather than sRGB-encoded
+    linear: bool,
+    /// How to transform the stored pixels to show the image upright
+    orientation: Orientation,
+    /// Lines for the info window: dimensions, format, file size and EXIF tags
+    metadata: String,
+    /// A still image has a single frame
+    frames: Vec<Frame>,
+}
+
+/// One frame of a [`DecodedImage`]
+struct Frame {
+    data: Vec<u8>,
+    /// How long the frame is shown
+    delay: Duration,
+}
+
+/// EXIF tags shown in the info window, if present
+const EXIF_TAGS: [Tag; 10] = [
+    Tag::Make,
+    Tag::Model,
+    Tag::LensModel,
+    Tag::DateTimeOriginal,
+    Tag::ExposureTime,
+    Tag::FNumber,
+    Tag::PhotographicSensitivity,
+    Tag::ExposureBiasValue,
+    Tag::FocalLength,
+    Tag::Flash,
+];
+
+fn open_image(path: impl AsRef<Path>) -> anyhow::Result<DecodedImage> {
+    let path = path.as_ref();
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    // Panoramas and scans easily exceed the default allocation limit.
+    reader.no_limits();
+    let format = reader.format();
+    if let Some(frames) = open_animation(path, format)? {
+        let (width, height) = frames[0].buffer().dimensions();
+        let mut metadata = describe_image(
+            path,
+            format,
+            (width, height),
+            ColorType::Rgba8,
+            Orientation::NoTransforms,
+            None,
+        );
+        metadata += &format!("\n{} frames", frames.len());
+        return Ok(DecodedImage {
+            width,
+            height,
+            format: TextureFormat::Rgba8Unorm,
+            linear: false,
+            orientation: Orientation::NoTransforms,
+            metadata,
+            frames: frames
+                .into_iter()
+                .map(|x| Frame {
+                    delay: frame_delay(x.delay().into()),
+                    data: x.into_buffer().into_raw(),
+                })
+                .collect(),
+        });
+    }
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let exif = decoder.exif_metadata()?;
+    let img = DynamicImage::from_decoder(decoder)?;
+    let metadata = describe_image(
+        path,
+        format,
+        (img.width(), img.height()),
+        img.color(),
+        orientation,
+        exif,
+    );
+
+    let (format, linear, data) = match img.color() {
+        ColorType::Rgb32F | ColorType::Rgba32F => {
+            (TextureFormat::Rgba16Float, true, to_f16_bytes(&img))
+        }
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            (TextureFormat::Rgba16Float, false, to_f16_bytes(&img))
+        }
+        _ => (TextureFormat::Rgba8Unorm, false, img.to_rgba8().into_raw()),
+    };
+    Ok(DecodedImage {
+        width: img.width(),
+        height: img.height(),
+        format,
+        linear,
+        orientation,
+        metadata,
+        frames: vec![Frame {
+            data,
+            delay: Duration::ZERO,
+        }],
+    })
+}
+
+/// Decodes all frames if `path` is an animated GIF, APNG or WebP.
+fn open_animation(
+    path: &Path,
+    format: Option<ImageFormat>,
+) -> anyhow::Result<Option<Vec<image::Frame>>> {
+    let reader = BufReader::new(File::open(path)?);
+    let frames = match format {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader)?.into_frames(),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader)?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames()
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    let frames = frames.collect_frames()?;
+    // single-frame GIFs are still images
+    Ok((frames.len() > 1).then_some(frames))
+}
+
+/// Like browsers, treat very short delays as the 100 ms many GIFs expect.
+fn frame_delay(delay: Duration) -> Duration {
+    if delay < Duration::from_millis(20) {
+        Duration::from_millis(100)
+    } else {
+        delay
+    }
+}
+
+fn describe_image(
+    path: &Path,
+    format: Option<ImageFormat>,
+    size: (u32, u32),
+    color: ColorType,
+    orientation: Orientation,
+    exif: Option<Vec<u8>>,
+) -> String {
+    use fmt::Write;
+    let mut text = format!("{}x{}", size.0, size.1);
+    if let Some(format) = format {
+        write!(&mut text, ", {:?}", format).unwrap();
+    }
+    write!(&mut text, ", {:?}", color).unwrap();
+    if let Ok(metadata) = fs::metadata(path) {
+        write!(&mut text, ", {}", format_file_size(metadata.len())).unwrap();
+    }
+    if orientation != Orientation::NoTransforms {
+        write!(&mut text, "\nOrientation: {:?}", orientation).unwrap();
+    }
 
-    // Do not handle large images (> 8192x8192) because the driver complains:
-    // wgpu error: Validation Error
-    //
-    // Caused by:
-    //   In Device::create_texture
-    //     Dimension Y value 10000 exceeds the limit of 8192
-    if width > 8192 || height > 8192 {
-        // texture-missing checkerboard pattern
-        #[rustfmt::skip]
-        return Ok((2, 2, vec![
-            0xff, 0x00, 0xff, 0xff,
-            0x00, 0x00, 0x00, 0xff,
-            0x00, 0x00, 0x00, 0xff,
-            0xff, 0x00, 0xff, 0xff,
-        ]));
+    let exif = exif.and_then(|x| exif::Reader::new().read_raw(x).ok());
+    if let Some(exif) = exif {
+        for tag in EXIF_TAGS {
+            if let Some(field) = exif.get_field(tag, In::PRIMARY) {
+                write!(
+                    &mut text,
+                    "\n{}: {}",
+                    tag,
+                    field.display_value().with_unit(&exif)
+                )
+                .unwrap();
+            }
+        }
     }
+    text
+}
 
-    let mut out_buf = Vec::with_capacity(width as usize * height as usize * 3);
-    for x in img.pixels() {
-        out_buf.push(x.2[0]);
-        out_buf.push(x.2[1]);
-        out_buf.push(x.2[2]);
-        // alpha
-        out_buf.push(255);
+fn format_file_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
     }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
 
-    Ok((width, height, out_buf))
+type DecodeResult = (usize, anyhow::Result<Arc<DecodedImage>>);
+
+/// Decodes images of the image list on a thread pool and keeps the most recently used ones,
+/// so that stepping through the list doesn't wait for the decoder.
+struct ImageCache {
+    pool: rayon::ThreadPool,
+    sender: Sender<DecodeResult>,
+    receiver: Receiver<DecodeResult>,
+    /// Indices into the image list; the most recently used one last
+    decoded: VecDeque<(usize, Arc<DecodedImage>)>,
+    pending: HashSet<usize>,
+    capacity: usize,
+}
+
+impl ImageCache {
+    fn new(capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            pool: rayon::ThreadPoolBuilder::new()
+                .thread_name(|i| format!("decoder-{}", i))
+                .build()
+                .unwrap(),
+            sender,
+            receiver,
+            decoded: VecDeque::new(),
+            pending: HashSet::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the decoded image and marks it as recently used.
+    fn get(&mut self, index: usize) -> Option<Arc<DecodedImage>> {
+        let position = self.decoded.iter().position(|x| x.0 == index)?;
+        let entry = self.decoded.remove(position).unwrap();
+        let image = Arc::clone(&entry.1);
+        self.decoded.push_back(entry);
+        Some(image)
+    }
+
+    /// Starts decoding `center` and the `radius` images on each side of it, nearest first.
+    fn prefetch(&mut self, list: &[PathBuf], center: usize, radius: usize) {
+        let len = list.len();
+        let mut wanted = vec![center];
+        for distance in 1..=radius.min(len / 2) {
+            wanted.push((center + distance) % len);
+            wanted.push((center + len - distance) % len);
+        }
+        wanted.dedup();
+
+        for &index in &wanted {
+            if self.pending.contains(&index) || self.decoded.iter().any(|x| x.0 == index) {
+                continue;
+            }
+            self.pending.insert(index);
+            let path = list[index].clone();
+            let sender = self.sender.clone();
+            self.pool.spawn(move || {
+                let result = open_image(&path).map(Arc::new);
+                // the receiver only goes away on exit
+                let _ = sender.send((index, result));
+            });
+        }
+        // Keep the wanted ones from being evicted, the center one the longest.
+        for &index in wanted.iter().rev() {
+            self.get(index);
+        }
+    }
+
+    /// Collects finished decodes.
+    fn poll(&mut self) -> Vec<DecodeResult> {
+        let finished = self.receiver.try_iter().collect::<Vec<_>>();
+        for (index, result) in &finished {
+            self.pending.remove(index);
+            if let Ok(image) = result {
+                self.decoded.push_back((*index, Arc::clone(image)));
+            }
+        }
+        while self.decoded.len() > self.capacity {
+            self.decoded.pop_front();
+        }
+        finished
+    }
+}
+
+fn to_f16_bytes(img: &DynamicImage) -> Vec<u8> {
+    img.to_rgba32f()
+        .into_raw()
+        .into_iter()
+        // HDR values can exceed the f16 range
+        .flat_map(|x| f16::from_f32(x.min(f16::MAX.to_f32())).to_le_bytes())
+        .collect()
 }
 
 #[derive(Parser)]
@@ -404,6 +965,17 @@ struct Args {
     no_scale: bool,
     #[arg(long, allow_hyphen_values = true, num_args = 1..)]
     info_cmd: Option<Vec<String>>,
+    /// Number of images before and after the current one to decode in advance
+    #[arg(long, default_value = "2")]
+    prefetch: usize,
+    /// Also show images in subfolders
+    #[arg(short = 'R', long)]
+    recursive: bool,
+    /// Order of the image list
+    #[arg(long, value_enum, default_value_t = SortOrder::Name)]
+    sort: SortOrder,
+    #[command(flatten)]
+    present: PresentArgs,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -423,27 +995,30 @@ fn main() -> anyhow::Result<()> {
         args.path.clone()
     };
 
-    let dir = fs::read_dir(dir_path)?;
-    for x in dir {
-        let entry = x?;
-        let path = entry.path();
-        if path.extension() == Some(OsStr::new("jpg"))
-            || path.extension() == Some(OsStr::new("png"))
+    list_images(&dir_path, args.recursive, &mut image_list)?;
+    let given_file = if args.path.is_dir() {
+        None
+    } else {
+        let path = args.path.canonicalize()?;
+        // Keep it even if it isn't recognized, so that the error is shown.
+        if !image_list
+            .iter()
+            .any(|x| x.canonicalize().ok().as_ref() == Some(&path))
         {
-            image_list.push(path);
+            image_list.push(args.path.clone());
         }
-    }
-    image_list.sort();
+        Some(path)
+    };
+    sort_images(&mut image_list, args.sort);
 
     // display the given image first
-    let mut image_index = 0_usize;
-    if !args.path.is_dir() {
-        let position = image_list
-            .iter()
-            .position(|x| x.canonicalize().unwrap() == args.path.canonicalize().unwrap())
-            .unwrap();
-        image_index = position;
-    }
+    let image_index = given_file
+        .and_then(|path| {
+            image_list
+                .iter()
+                .position(|x| x.canonicalize().ok().as_ref() == Some(&path))
+        })
+        .unwrap_or_default();
 
     if image_list.is_empty() {
         eprintln!("There's no image in the folder");
@@ -461,6 +1036,11 @@ fn main() -> anyhow::Result<()> {
         args: &args,
         info_window: None,
         info_state: None,
+        modifiers: ModifiersState::empty(),
+        dragging: false,
+        cursor_position: (0.0, 0.0),
+        // the prefetched ones plus a few recently viewed
+        images: ImageCache::new(args.prefetch * 2 + 3),
     };
     el.run_app(&mut app)?;
     Ok(())
@@ -472,11 +1052,137 @@ struct Uniform {
     image_size: [u32; 2],
     out_size: [u32; 2],
     uv_offset: [f32; 2],
-    _pad1: [u32; 2],
+    /// Zoom relative to fitting the image in the window
+    scale: f32,
+    tile_cols: u32,
+    tile_size: [u32; 2],
     no_scale: WgpuBool,
     proportional: WgpuBool,
-    _pad2: u32,
-    _pad3: u32,
+    /// In EV; only applied to linear images
+    exposure: f32,
+    tone_map: WgpuBool,
+    linear_input: WgpuBool,
+    /// Bicubic instead of bilinear filtering when magnifying
+    bicubic: WgpuBool,
+    /// See [`orientation_code`]
+    orientation: u32,
+    /// Added to the displayed values
+    brightness: f32,
+    /// Scales the displayed values around mid-gray
+    contrast: f32,
+    gamma: f32,
+    /// 0 is grayscale; 1 keeps the colors
+    saturation: f32,
+    invert: WgpuBool,
+    /// A [`ChannelView`]
+    channel: u32,
+    /// Output the image's alpha instead of compositing over the checkerboard
+    keep_alpha: WgpuBool,
+}
+
+impl Uniform {
+    fn reset_adjustments(&mut self) {
+        self.brightness = 0.0;
+        self.contrast = 1.0;
+        self.gamma = 1.0;
+        self.saturation = 1.0;
+        self.invert = false.into();
+        self.channel = ChannelView::Rgb as u32;
+    }
+}
+
+/// What the viewer shows of the image's channels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+enum ChannelView {
+    Rgb,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luminance,
+}
+
+impl ChannelView {
+    const ALL: [Self; 6] = [
+        Self::Rgb,
+        Self::Red,
+        Self::Green,
+        Self::Blue,
+        Self::Alpha,
+        Self::Luminance,
+    ];
+
+    fn from_code(code: u32) -> Self {
+        Self::ALL[code as usize]
+    }
+
+    fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// The adjustment `↑`/`↓` change
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Adjustment {
+    Brightness,
+    Contrast,
+    Gamma,
+    Saturation,
+}
+
+impl Adjustment {
+    fn next(self) -> Self {
+        match self {
+            Adjustment::Brightness => Adjustment::Contrast,
+            Adjustment::Contrast => Adjustment::Gamma,
+            Adjustment::Gamma => Adjustment::Saturation,
+            Adjustment::Saturation => Adjustment::Brightness,
+        }
+    }
+}
+
+/// Encodes an [`Orientation`] for the shader: bits 0-1 are the clockwise quarter turns, bit 2
+/// flips horizontally after rotating.
+fn orientation_code(orientation: Orientation) -> u32 {
+    const FLIP: u32 = 4;
+    match orientation {
+        Orientation::NoTransforms => 0,
+        Orientation::Rotate90 => 1,
+        Orientation::Rotate180 => 2,
+        Orientation::Rotate270 => 3,
+        Orientation::FlipHorizontal => FLIP,
+        Orientation::Rotate90FlipH => 1 | FLIP,
+        // a vertical flip is a half turn plus a horizontal flip
+        Orientation::FlipVertical => 2 | FLIP,
+        Orientation::Rotate270FlipH => 3 | FLIP,
+    }
+}
+
+/// Composes a clockwise quarter turn onto an orientation code; see [`orientation_code`].
+fn rotate_code(code: u32) -> u32 {
+    if code & 4 == 0 {
+        (code + 1) & 3
+    } else {
+        // turning a flipped image clockwise turns the stored one counterclockwise
+        ((code + 3) & 3) | 4
+    }
+}
+
+/// Maps a texture coordinate in the shown image to one in the stored image. Mirrors
+/// `orient_uv` in the shader.
+fn orient_uv(orientation: u32, uv: [f32; 2]) -> [f32; 2] {
+    let mut p = [uv[0] - 0.5, uv[1] - 0.5];
+    if orientation & 4 != 0 {
+        p[0] = -p[0];
+    }
+    p = match orientation & 3 {
+        1 => [p[1], -p[0]],
+        2 => [-p[0], -p[1]],
+        3 => [-p[1], p[0]],
+        _ => p,
+    };
+    [p[0] + 0.5, p[1] + 0.5]
 }
 
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
@@ -507,15 +1213,250 @@ impl From<bool> for WgpuBool {
     }
 }
 
-assert_eq_size!(Uniform, [u32; 12]);
+assert_eq_size!(Uniform, [u32; 24]);
+
+#[derive(Debug, Copy, Clone)]
+enum ViewPreset {
+    /// The whole image is visible
+    Fit,
+    /// One image pixel per window pixel
+    Actual,
+    /// The image covers the whole window
+    Fill,
+}
+
+/// Fills the mip levels of a texture by repeatedly downsampling the previous level.
+struct MipmapGenerator {
+    module: ShaderModule,
+    sampler: Sampler,
+    /// One per texture format
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    fn new(device: &Device) -> Self {
+        Self {
+            module: device.create_shader_module(include_wgsl!("../shaders/mipmap.wgsl")),
+            sampler: device.create_sampler(&SamplerDescriptor {
+                label: None,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..default!()
+            }),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    fn pipeline(&mut self, device: &Device, format: TextureFormat) -> &RenderPipeline {
+        self.pipelines.entry(format).or_insert_with(|| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("mipmap"),
+                layout: None,
+                vertex: VertexState {
+                    module: &self.module,
+                    entry_point: None,
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: Some(FragmentState {
+                    module: &self.module,
+                    entry_point: None,
+                    compilation_options: Default::default(),
+                    targets: &[Some(format.into())],
+                }),
+                multiview_mask: None,
+                cache: None,
+            })
+        })
+    }
+
+    /// Generates levels `1..` of every layer from level 0.
+    fn generate(&mut self, device: &Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let pipeline = self.pipeline(device, texture.format()).clone();
+        let mut encoder = device.create_command_encoder(&default!());
+        for layer in 0..texture.depth_or_array_layers() {
+            let view = |level| {
+                texture.create_view(&TextureViewDescriptor {
+                    dimension: Some(TextureViewDimension::D2),
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..default!()
+                })
+            };
+            for level in 1..texture.mip_level_count() {
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: None,
+                    layout: &pipeline.get_bind_group_layout(0),
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::Sampler(&self.sampler),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(&view(level - 1)),
+                        },
+                    ],
+                });
+                let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &view(level),
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::DontCare(LoadOpDontCare::default()),
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                    multiview_mask: None,
+                });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+        queue.submit([encoder.finish()]);
+    }
+}
+
+/// How an image is split into tiles that each fit in a texture.
+///
+/// Images larger than the max texture dimension can't be uploaded as one texture, so they are
+/// split into equally sized tiles stored as layers of a texture array, row by row. Tiles in
+/// the last column/row may be only partially filled; see [`TileGrid::upload`].
+#[derive(Debug, Copy, Clone)]
+struct TileGrid {
+    image_size: (u32, u32),
+    tile_size: (u32, u32),
+    cols: u32,
+    rows: u32,
+}
+
+impl TileGrid {
+    fn new(image_size: (u32, u32), max_dimension: u32) -> Self {
+        let cols = image_size.0.div_ceil(max_dimension);
+        let rows = image_size.1.div_ceil(max_dimension);
+        Self {
+            image_size,
+            tile_size: (image_size.0.div_ceil(cols), image_size.1.div_ceil(rows)),
+            cols,
+            rows,
+        }
+    }
+
+    fn layers(&self) -> u32 {
+        self.cols * self.rows
+    }
+
+    /// Origin and size of the image region stored in `layer`
+    fn tile_rect(&self, layer: u32) -> ((u32, u32), (u32, u32)) {
+        let origin = (
+            layer % self.cols * self.tile_size.0,
+            layer / self.cols * self.tile_size.1,
+        );
+        let size = (
+            self.tile_size.0.min(self.image_size.0 - origin.0),
+            self.tile_size.1.min(self.image_size.1 - origin.1),
+        );
+        (origin, size)
+    }
+
+    /// Writes `data`, the tightly packed pixels of the whole image, to level 0 of `texture`.
+    ///
+    /// The unused part of partial tiles gets copies of the edge pixels, so that the mip
+    /// levels don't average in the zero-initialized texels there.
+    fn upload(&self, queue: &wgpu::Queue, texture: &wgpu::Texture, data: &[u8]) {
+        let pixel_size = texture.format().block_copy_size(None).unwrap();
+        let width = self.image_size.0;
+        for layer in 0..self.layers() {
+            let (origin, size) = self.tile_rect(layer);
+            let padded;
+            let (bytes, layout) = if size == self.tile_size {
+                let layout = TexelCopyBufferLayout {
+                    offset: (origin.1 as u64 * width as u64 + origin.0 as u64) * pixel_size as u64,
+                    bytes_per_row: Some(width * pixel_size),
+                    rows_per_image: None,
+                };
+                (data, layout)
+            } else {
+                padded = self.padded_tile(data, pixel_size as usize, origin, size);
+                let layout = TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.tile_size.0 * pixel_size),
+                    rows_per_image: None,
+                };
+                (&padded[..], layout)
+            };
+            queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                    aspect: Default::default(),
+                },
+                bytes,
+                layout,
+                Extent3d {
+                    width: self.tile_size.0,
+                    height: self.tile_size.1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    /// The pixels of the partial tile at `origin` with `size`, extended to the full tile size
+    /// by repeating the last column and row.
+    fn padded_tile(
+        &self,
+        data: &[u8],
+        pixel_size: usize,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> Vec<u8> {
+        let (tile_width, tile_height) = self.tile_size;
+        let mut tile = Vec::with_capacity(tile_width as usize * tile_height as usize * pixel_size);
+        for y in 0..tile_height {
+            let image_y = (origin.1 + y.min(size.1 - 1)) as usize;
+            let start = (image_y * self.image_size.0 as usize + origin.0 as usize) * pixel_size;
+            let row = &data[start..][..size.0 as usize * pixel_size];
+            tile.extend_from_slice(row);
+            let last_pixel = &row[row.len() - pixel_size..];
+            for _ in size.0..tile_width {
+                tile.extend_from_slice(last_pixel);
+            }
+        }
+        tile
+    }
+}
+
+/// Playback position of an animated image
+struct Playback {
+    tiles: TileGrid,
+    frame: usize,
+    /// When `frame` was due to be shown
+    shown_at: Instant,
+    paused: bool,
+}
 
 struct State {
     device: Device,
     pipeline: RenderPipeline,
     queue: wgpu::Queue,
-    surface_format: TextureFormat,
-    surface: Surface<'static>,
-    size: (u32, u32),
+    surface: SurfaceManager,
     bind_group: Option<wgpu::BindGroup>,
     uniform: Buffer,
     uniform_data: Uniform,
@@ -523,6 +1464,12 @@ struct State {
     linear_sampler: wgpu::Sampler,
     current_image_size: (u32, u32),
     current_image_texture: Option<wgpu::Texture>,
+    /// Kept for the pixel probe and animation frames
+    current_image: Option<Arc<DecodedImage>>,
+    mipmaps: MipmapGenerator,
+    /// Only for animated images
+    playback: Option<Playback>,
+    adjustment: Adjustment,
 }
 
 impl State {
@@ -531,13 +1478,16 @@ impl State {
         surface: Surface<'static>,
         init_size: (u32, u32),
         no_scale: bool,
+        present: PresentConfig,
     ) -> anyhow::Result<Self> {
-        let adapter = pollster::block_on(instance.request_adapter(&default!()))?;
+        let adapter =
+            pollster::block_on(AdapterSelector::from_env().select(&instance, Some(&surface)))?;
         let (device, queue) = pollster::block_on(adapter.request_device(&default!()))?;
 
-        let caps = surface.get_capabilities(&adapter);
         // disable auto gamma encoding
-        let surface_format = caps.formats[0].remove_srgb_suffix();
+        let mut surface =
+            SurfaceManager::new(surface, &adapter, &device, init_size, SrgbPolicy::NoSrgb);
+        surface.set_present_config(present)?;
 
         let module = device.create_shader_module(include_wgsl!("../shaders/image-viewer.wgsl"));
 
@@ -558,7 +1508,7 @@ impl State {
                 entry_point: None,
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: surface_format,
+                    format: surface.format(),
                     blend: None,
                     write_mask: Default::default(),
                 })],
@@ -586,101 +1536,186 @@ impl State {
             label: None,
             mag_filter: FilterMode::Linear,
             min_filter: FilterMode::Linear,
+            mipmap_filter: MipmapFilterMode::Linear,
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             ..default!()
         });
+        let mipmaps = MipmapGenerator::new(&device);
 
         let mut state = Self {
             device,
             pipeline,
             queue,
-            surface_format,
             surface,
-            size: init_size,
             bind_group: None,
             uniform,
             uniform_data: {
                 let mut u = Uniform::zeroed();
                 u.proportional = true.into();
+                u.scale = 1.0;
+                u.tone_map = true.into();
+                u.reset_adjustments();
                 u
             },
             nearest_sampler,
             linear_sampler,
             current_image_size: (0, 0),
             current_image_texture: None,
+            current_image: None,
+            mipmaps,
+            playback: None,
+            adjustment: Adjustment::Brightness,
         };
         state.uniform_data.no_scale = no_scale.into();
         state.write_uniform();
-
-        // run an initial surface configuration
-        state.reconfigure_surface();
         Ok(state)
     }
 
-    fn update_image(&mut self, file: impl AsRef<Path>) -> anyhow::Result<()> {
-        let file = file.as_ref();
-        info!("Set image: {}", file.display());
-        let (width, height, image_buf) = open_image(file)?;
+    fn set_image(&mut self, image: &Arc<DecodedImage>) -> anyhow::Result<()> {
+        let (width, height) = (image.width, image.height);
+
+        let limits = self.device.limits();
+        let tiles = TileGrid::new((width, height), limits.max_texture_dimension_2d);
+        if tiles.layers() > limits.max_texture_array_layers {
+            bail!(
+                "Image {}x{} needs {} tiles; the device supports {}",
+                width,
+                height,
+                tiles.layers(),
+                limits.max_texture_array_layers
+            );
+        }
+        if tiles.layers() > 1 {
+            info!("Split the image into {}x{} tiles", tiles.cols, tiles.rows);
+        }
 
         let texture = self.device.create_texture(&TextureDescriptor {
             label: None,
             size: Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
+                width: tiles.tile_size.0,
+                height: tiles.tile_size.1,
+                depth_or_array_layers: tiles.layers(),
             },
             sample_count: 1,
-            mip_level_count: 1,
+            mip_level_count: tiles.tile_size.0.max(tiles.tile_size.1).ilog2() + 1,
             dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[TextureFormat::Rgba8UnormSrgb],
+            format: image.format,
+            usage: TextureUsages::COPY_DST
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
         });
 
-        self.queue.write_texture(
-            TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: Default::default(),
-                aspect: Default::default(),
-            },
-            &image_buf,
-            TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(width * 4 ),
-                rows_per_image: None,
-            },
-            Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-        );
+        tiles.upload(&self.queue, &texture, &image.frames[0].data);
+        self.mipmaps.generate(&self.device, &self.queue, &texture);
         self.current_image_texture = Some(texture);
-        self.current_image_size = (width, height);
+        self.current_image = Some(Arc::clone(image));
+        self.playback = (image.frames.len() > 1).then(|| Playback {
+            tiles,
+            frame: 0,
+            shown_at: Instant::now(),
+            paused: false,
+        });
+        let orientation = orientation_code(image.orientation);
+        // width and height as shown
+        self.current_image_size = if orientation & 1 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        };
         self.uniform_data.image_size = [width, height];
+        self.uniform_data.orientation = orientation;
+        self.uniform_data.tile_size = tiles.tile_size.into();
+        self.uniform_data.tile_cols = tiles.cols;
+        self.uniform_data.linear_input = image.linear.into();
+        self.uniform_data.scale = 1.0;
+        self.uniform_data.uv_offset = [0.0; 2];
         self.write_uniform();
         self.configure_bind_group();
         Ok(())
     }
 
+    /// Uploads frame `frame` of the animation.
+    fn show_frame(&mut self, frame: usize) {
+        let (Some(playback), Some(texture), Some(image)) = (
+            &mut self.playback,
+            &self.current_image_texture,
+            &self.current_image,
+        ) else {
+            return;
+        };
+        playback.frame = frame;
+        let data = &image.frames[frame].data;
+        playback.tiles.upload(&self.queue, texture, data);
+        self.mipmaps.generate(&self.device, &self.queue, texture);
+    }
+
+    /// Moves the animation to the frame due now.
+    fn advance_animation(&mut self) {
+        let (Some(playback), Some(image)) = (&mut self.playback, &self.current_image) else {
+            return;
+        };
+        if playback.paused {
+            return;
+        }
+        let frames = &image.frames;
+        let now = Instant::now();
+        let total = frames.iter().map(|x| x.delay).sum::<Duration>();
+        if now - playback.shown_at > total {
+            // we've been away for a whole loop (e.g. hidden); just go on from here
+            playback.shown_at = now;
+            return;
+        }
+        let mut frame = playback.frame;
+        while now - playback.shown_at >= frames[frame].delay {
+            playback.shown_at += frames[frame].delay;
+            frame = (frame + 1) % frames.len();
+        }
+        if frame != playback.frame {
+            self.show_frame(frame);
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        if let Some(playback) = &mut self.playback {
+            playback.paused = !playback.paused;
+            playback.shown_at = Instant::now();
+        }
+    }
+
+    /// Pauses and shows the next or previous frame.
+    fn step_frame(&mut self, forward: bool) {
+        let (Some(playback), Some(image)) = (&mut self.playback, &self.current_image) else {
+            return;
+        };
+        playback.paused = true;
+        let len = image.frames.len();
+        let frame = if forward {
+            (playback.frame + 1) % len
+        } else {
+            (playback.frame + len - 1) % len
+        };
+        self.show_frame(frame);
+    }
+
     fn configure_bind_group(&mut self) {
-        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+        self.bind_group = Some(self.create_bind_group(&self.uniform));
+    }
+
+    fn create_bind_group(&self, uniform: &Buffer) -> wgpu::BindGroup {
+        self.device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &self.pipeline.get_bind_group_layout(0),
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: self.uniform.as_entire_binding(),
+                    resource: uniform.as_entire_binding(),
                 },
                 BindGroupEntry {
                     binding: 1,
                     resource: BindingResource::Sampler(
-                        if (self.current_image_size.0 <= self.size.0
-                            && self.current_image_size.1 <= self.size.1)
-                            || self.uniform_data.no_scale.to_bool()
-                        {
+                        if self.zoom_level() >= 1.0 || self.uniform_data.no_scale.to_bool() {
                             &self.nearest_sampler
                         } else {
                             &self.linear_sampler
@@ -694,12 +1729,286 @@ impl State {
                             .current_image_texture
                             .as_ref()
                             .expect("Current image texture is required")
-                            .create_view(&TextureViewDescriptor::default()),
+                            .create_view(&TextureViewDescriptor {
+                                // a single tile would otherwise get a plain D2 view
+                                dimension: Some(TextureViewDimension::D2Array),
+                                ..default!()
+                            }),
                     ),
                 },
             ],
+        })
+    }
+
+    /// Rotates the shown image by 90° and fits it in the window.
+    fn rotate(&mut self, clockwise: bool) {
+        let turns = if clockwise { 1 } else { 3 };
+        for _ in 0..turns {
+            self.uniform_data.orientation = rotate_code(self.uniform_data.orientation);
+        }
+        let (width, height) = self.current_image_size;
+        self.current_image_size = (height, width);
+        self.set_view(ViewPreset::Fit);
+    }
+
+    fn flip(&mut self, vertical: bool) {
+        let mut code = self.uniform_data.orientation ^ 4;
+        if vertical {
+            // a vertical flip is a horizontal one plus a half turn
+            code = rotate_code(rotate_code(code));
+        }
+        self.uniform_data.orientation = code;
+    }
+
+    /// Renders the image at its full size with the current orientation and adjustments.
+    fn render_to_image(&self) -> anyhow::Result<image::RgbaImage> {
+        if self.current_image.is_none() {
+            bail!("No image to save");
+        }
+        let (width, height) = self.current_image_size;
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        if width > max_dimension || height > max_dimension {
+            bail!(
+                "Image {}x{} exceeds the max texture dimension {}",
+                width,
+                height,
+                max_dimension
+            );
+        }
+        // the pipeline renders to the surface format
+        let format = self.surface.format();
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            sample_count: 1,
+            mip_level_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let mut uniform_data = self.uniform_data;
+        uniform_data.out_size = [width, height];
+        // one output pixel per image pixel
+        uniform_data.no_scale = true.into();
+        uniform_data.keep_alpha = true.into();
+        let uniform = self.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: size_of::<Uniform>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
         });
-        self.bind_group = Some(bind_group);
+        self.queue
+            .write_buffer(&uniform, 0, bytes_of(&uniform_data));
+
+        let mut encoder = self.device.create_command_encoder(&default!());
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &texture.create_view(&default!()),
+                depth_slice: None,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::DontCare(LoadOpDontCare::default()),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.create_bind_group(&uniform), &[]);
+        pass.draw(0..6, 0..1);
+        drop(pass);
+        self.queue.submit([encoder.finish()]);
+
+        let mut data = pollster::block_on(read_texture(&self.device, &self.queue, &texture))?;
+        match format {
+            TextureFormat::Rgba8Unorm => {}
+            TextureFormat::Bgra8Unorm => {
+                for pixel in data.chunks_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            _ => bail!("Saving from a {:?} surface is not supported", format),
+        }
+        Ok(image::RgbaImage::from_raw(width, height, data).unwrap())
+    }
+
+    /// Window pixels per image pixel when the image fits in the window (`scale == 1`)
+    fn fit_factor(&self) -> f32 {
+        let (width, height) = self.current_image_size;
+        let (out_width, out_height) = self.surface.size();
+        let x = out_width as f32 / width as f32;
+        let y = out_height as f32 / height as f32;
+        x.min(y)
+    }
+
+    /// Window pixels per image pixel
+    fn zoom_level(&self) -> f32 {
+        self.uniform_data.scale * self.fit_factor()
+    }
+
+    fn status_text(&self) -> String {
+        let mut text = format!("Zoom: {:.0}%", self.zoom_level() * 100.0);
+        if self.uniform_data.linear_input.to_bool() {
+            text += &format!(
+                ", exposure: {:+.1} EV, tone mapping: {}",
+                self.uniform_data.exposure,
+                if self.uniform_data.tone_map.to_bool() {
+                    "on"
+                } else {
+                    "off"
+                }
+            );
+        }
+        if let (Some(playback), Some(image)) = (&self.playback, &self.current_image) {
+            text += &format!(
+                ", frame: {}/{}{}",
+                playback.frame + 1,
+                image.frames.len(),
+                if playback.paused { " (paused)" } else { "" }
+            );
+        }
+
+        let u = &self.uniform_data;
+        text += &format!(
+            "\nAdjusting {:?}; brightness: {:+.2}, contrast: {:.2}, gamma: {:.2}, saturation: {:.1}",
+            self.adjustment, u.brightness, u.contrast, u.gamma, u.saturation
+        );
+        if u.invert.to_bool() {
+            text += ", inverted";
+        }
+        let channel = ChannelView::from_code(u.channel);
+        if channel != ChannelView::Rgb {
+            text += &format!(", showing {:?}", channel);
+        }
+        text
+    }
+
+    /// Changes the selected adjustment by `steps` steps.
+    fn adjust(&mut self, steps: f32) {
+        let u = &mut self.uniform_data;
+        match self.adjustment {
+            Adjustment::Brightness => u.brightness += 0.05 * steps,
+            Adjustment::Contrast => u.contrast *= 1.1_f32.powf(steps),
+            Adjustment::Gamma => u.gamma *= 1.1_f32.powf(steps),
+            Adjustment::Saturation => u.saturation = (u.saturation + 0.1 * steps).max(0.0),
+        }
+    }
+
+    /// Describes the image pixel under the window position `position`.
+    fn probe(&self, position: (f64, f64)) -> Option<String> {
+        let image = self.current_image.as_ref()?;
+        let (width, height) = self.current_image_size;
+        // texture coordinate in the shown image; mirrors `fs`
+        let shown_uv = if self.uniform_data.no_scale.to_bool() {
+            [
+                (position.0.floor() as f32 + 0.5) / width as f32,
+                (position.1.floor() as f32 + 0.5) / height as f32,
+            ]
+        } else {
+            let uv = self.fitted_uv(position);
+            let (scale, offset) = (self.uniform_data.scale, self.uniform_data.uv_offset);
+            [
+                (uv[0] - 0.5) / scale + 0.5 + offset[0],
+                (uv[1] - 0.5) / scale + 0.5 + offset[1],
+            ]
+        };
+        if shown_uv.iter().any(|x| !(0.0..1.0).contains(x)) {
+            return None;
+        }
+        let uv = orient_uv(self.uniform_data.orientation, shown_uv);
+        let x = ((uv[0] * image.width as f32) as u32).min(image.width - 1);
+        let y = ((uv[1] * image.height as f32) as u32).min(image.height - 1);
+
+        let frame = self.playback.as_ref().map_or(0, |x| x.frame);
+        let pixel_size = image.format.block_copy_size(None).unwrap() as usize;
+        let offset = (y as usize * image.width as usize + x as usize) * pixel_size;
+        let pixel = &image.frames[frame].data[offset..][..pixel_size];
+        let value = match image.format {
+            TextureFormat::Rgba16Float => {
+                let c = pixel
+                    .chunks(2)
+                    .map(|x| f16::from_le_bytes([x[0], x[1]]).to_f32())
+                    .collect::<Vec<_>>();
+                format!("R {:.3} G {:.3} B {:.3} A {:.3}", c[0], c[1], c[2], c[3])
+            }
+            _ => format!(
+                "R {} G {} B {} A {}",
+                pixel[0], pixel[1], pixel[2], pixel[3]
+            ),
+        };
+        Some(format!("Pixel ({}, {}): {}", x, y, value))
+    }
+
+    fn set_view(&mut self, preset: ViewPreset) {
+        let (width, height) = self.current_image_size;
+        let (out_width, out_height) = self.surface.size();
+        let x = out_width as f32 / width as f32;
+        let y = out_height as f32 / height as f32;
+        self.uniform_data.uv_offset = [0.0; 2];
+        self.uniform_data.scale = match preset {
+            ViewPreset::Fit => 1.0,
+            ViewPreset::Actual => 1.0 / self.fit_factor(),
+            ViewPreset::Fill => x.max(y) / x.min(y),
+        };
+        self.configure_bind_group();
+    }
+
+    /// Maps a window position to the texture coordinate the shader samples there at
+    /// `scale == 1` without offset. Mirrors the start of `fs`.
+    fn fitted_uv(&self, position: (f64, f64)) -> [f32; 2] {
+        let (width, height) = self.current_image_size;
+        let (out_width, out_height) = self.surface.size();
+        let mut uv = [
+            position.0 as f32 / out_width as f32,
+            position.1 as f32 / out_height as f32,
+        ];
+        if self.uniform_data.proportional.to_bool() {
+            let input_aspect = width as f32 / height as f32;
+            let output_aspect = out_width as f32 / out_height as f32;
+            if input_aspect > output_aspect {
+                let s = output_aspect / input_aspect;
+                uv[1] = (uv[1] - 0.5) / s + 0.5;
+            } else {
+                let s = input_aspect / output_aspect;
+                uv[0] = (uv[0] - 0.5) / s + 0.5;
+            }
+        }
+        uv
+    }
+
+    /// Zooms by `factor`, keeping the image point under `position` in place.
+    fn zoom_at(&mut self, position: (f64, f64), factor: f32) {
+        let old_scale = self.uniform_data.scale;
+        // from a tenth of the fitting size up to 64 window pixels per image pixel
+        let new_scale = (old_scale * factor).min(64.0 / self.fit_factor()).max(0.1);
+        let uv = self.fitted_uv(position);
+        let offset = &mut self.uniform_data.uv_offset;
+        for i in 0..2 {
+            offset[i] += (uv[i] - 0.5) * (1.0 / old_scale - 1.0 / new_scale);
+        }
+        self.uniform_data.scale = new_scale;
+        self.configure_bind_group();
+    }
+
+    /// Moves the image along with a cursor movement of `delta` window pixels.
+    fn pan(&mut self, delta: (f64, f64)) {
+        let origin = self.fitted_uv((0.0, 0.0));
+        let moved = self.fitted_uv(delta);
+        let scale = self.uniform_data.scale;
+        let offset = &mut self.uniform_data.uv_offset;
+        for i in 0..2 {
+            offset[i] -= (moved[i] - origin[i]) / scale;
+        }
     }
 
     fn write_uniform(&self) {
@@ -708,28 +2017,11 @@ impl State {
     }
 
     fn resize(&mut self, size: (u32, u32)) {
-        self.size = size;
-        self.reconfigure_surface();
-    }
-
-    fn reconfigure_surface(&mut self) {
-        info!(
-            "Configure surface: width {}, height {}",
-            self.size.0, self.size.1
-        );
-        self.surface.configure(
-            &self.device,
-            &SurfaceConfiguration {
-                usage: TextureUsages::RENDER_ATTACHMENT,
-                format: self.surface_format,
-                width: self.size.0,
-                height: self.size.1,
-                present_mode: Default::default(),
-                desired_maximum_frame_latency: 0,
-                alpha_mode: Default::default(),
-                view_formats: vec![self.surface_format],
-            },
-        );
+        self.surface.resize(size);
+        // the sampler depends on the zoom level, which depends on the window size
+        if self.current_image_texture.is_some() {
+            self.configure_bind_group();
+        }
     }
 
     fn render(&mut self, pre_present_op: impl FnOnce()) {
@@ -738,7 +2030,8 @@ impl State {
             return;
         }
 
-        self.uniform_data.out_size = self.size.into();
+        self.advance_animation();
+        self.uniform_data.out_size = self.surface.size().into();
 
         // let subsec = Local::now().timestamp_subsec_nanos() as f64 / 1_000_000_000.0;
         // let offset = subsec * 1000000.0;
@@ -747,40 +2040,14 @@ impl State {
 
         let mut encoder = self.device.create_command_encoder(&default!());
 
-        let texture = match self.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(texture) => texture,
-            wgpu::CurrentSurfaceTexture::Suboptimal(texture) => {
-                self.reconfigure_surface();
-                texture
-            }
-            wgpu::CurrentSurfaceTexture::Timeout | wgpu::CurrentSurfaceTexture::Occluded => {
-                return;
-            }
-            wgpu::CurrentSurfaceTexture::Outdated | wgpu::CurrentSurfaceTexture::Lost => {
-                self.reconfigure_surface();
-                return;
-            }
-            wgpu::CurrentSurfaceTexture::Validation => {
-                error!("Validation error in get_current_texture");
-                return;
-            }
+        let Some(frame) = self.surface.acquire() else {
+            return;
         };
-        let texture_view = texture.texture.create_view(&TextureViewDescriptor {
-            label: None,
-            format: Some(self.surface_format),
-            dimension: Some(TextureViewDimension::D2),
-            usage: Some(TextureUsages::RENDER_ATTACHMENT),
-            aspect: Default::default(),
-            base_mip_level: 0,
-            mip_level_count: None,
-            base_array_layer: 0,
-            array_layer_count: None,
-        });
 
         let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(RenderPassColorAttachment {
-                view: &texture_view,
+                view: &frame.view,
                 depth_slice: None,
                 resolve_target: None,
                 ops: Operations {
@@ -802,6 +2069,6 @@ impl State {
 
         self.queue.submit([command_buffer]);
         pre_present_op();
-        texture.present();
+        frame.present();
     }
 }
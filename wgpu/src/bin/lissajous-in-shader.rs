@@ -1,17 +1,16 @@
-use log::error;
 use palette::{FromColor, Srgb};
 use std::env;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
 use wgpu::VertexFormat::Float32x2;
 use wgpu::util::RenderEncoder;
 use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferDescriptor, BufferUsages,
-    Color, ColorTargetState, CurrentSurfaceTexture, Device, FragmentState,
-    PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
-    VertexAttribute, VertexBufferLayout, VertexState, include_wgsl,
+    Color, ColorTargetState, Device, FragmentState, PrimitiveState, PrimitiveTopology,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, VertexAttribute, VertexBufferLayout,
+    VertexState, include_wgsl,
 };
-use wgpu_playground::wgpu_instance_with_env_backend;
+use wgpu_playground::surface::{SrgbPolicy, SurfaceManager};
+use wgpu_playground::{AdapterSelector, FrameStats, wgpu_instance_with_env_backend};
 use winit::event::{ElementState, MouseButton};
 use winit::keyboard::{Key, NamedKey};
 use winit::{
@@ -22,13 +21,11 @@ use winit::{
 };
 
 struct State {
-    elapsed: PausableTimeElapse,
+    frame_stats: FrameStats,
     window: Arc<Window>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    size: winit::dpi::PhysicalSize<u32>,
-    surface: wgpu::Surface<'static>,
-    surface_format: wgpu::TextureFormat,
+    surface: SurfaceManager,
     module: ShaderModule,
     pipeline: RenderPipeline,
     vertex_buffer: Buffer,
@@ -42,8 +39,8 @@ impl State {
         //     wgpu::InstanceDescriptor::default().with_display_handle(Box::new(display)),
         // );
         let instance = wgpu_instance_with_env_backend();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
+        let adapter = AdapterSelector::from_env()
+            .select(&instance, None)
             .await
             .unwrap();
         let (device, queue) = adapter
@@ -54,9 +51,13 @@ impl State {
         let size = window.inner_size();
 
         let surface = instance.create_surface(window.clone()).unwrap();
-        let cap = surface.get_capabilities(&adapter);
-
-        let surface_format = cap.formats[0];
+        let surface = SurfaceManager::new(
+            surface,
+            &adapter,
+            &device,
+            (size.width, size.height),
+            SrgbPolicy::Srgb,
+        );
 
         let shader_module =
             device.create_shader_module(include_wgsl!("../shaders/lissajous-in-shader.wgsl"));
@@ -87,7 +88,7 @@ impl State {
                 entry_point: None,
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: surface_format.add_srgb_suffix(),
+                    format: surface.format(),
                     blend: None,
                     write_mask: Default::default(),
                 })],
@@ -111,29 +112,21 @@ impl State {
             usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
             mapped_at_creation: false,
         });
-        let state = State {
-            elapsed: PausableTimeElapse::new(),
+        State {
+            frame_stats: FrameStats::default(),
             window,
             device,
             queue,
-            size,
             surface,
-            surface_format,
             module: shader_module,
             pipeline,
             vertex_buffer,
             uniform: Default::default(),
             uniform_buffer,
-        };
-
-        // Configure surface for the first time
-        state.configure_surface();
-
-        state
+        }
     }
 
     fn create_vertex_buffer(device: &Device, size: u64) -> Buffer {
-        
         device.create_buffer(&BufferDescriptor {
             label: None,
             size,
@@ -146,58 +139,16 @@ impl State {
         &self.window
     }
 
-    fn configure_surface(&self) {
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: self.surface_format,
-            // Request compatibility with the sRGB-format texture view we‘re going to create later.
-            view_formats: vec![self.surface_format.add_srgb_suffix()],
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            width: self.size.width,
-            height: self.size.height,
-            desired_maximum_frame_latency: 2,
-            present_mode: wgpu::PresentMode::AutoVsync,
-        };
-        self.surface.configure(&self.device, &surface_config);
-    }
-
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.size = new_size;
-
-        // reconfigure the surface
-        self.configure_surface();
+        self.surface.resize((new_size.width, new_size.height));
     }
 
     fn render(&mut self) {
-        // Create texture view
-        let surface_texture = match self.surface.get_current_texture() {
-            CurrentSurfaceTexture::Success(texture) => texture,
-            CurrentSurfaceTexture::Suboptimal(texture) => {
-                self.configure_surface();
-                texture
-            }
-            CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
-                return;
-            }
-            CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
-                self.configure_surface();
-                return;
-            }
-            CurrentSurfaceTexture::Validation => {
-                error!("Validation error in get_current_texture");
-                return;
-            }
+        let Some(frame) = self.surface.acquire() else {
+            return;
         };
-        let texture_view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor {
-                // Without add_srgb_suffix() the image we will be working with
-                // might not be "gamma correct".
-                format: Some(self.surface_format.add_srgb_suffix()),
-                ..Default::default()
-            });
 
-        let elapsed = self.elapsed.elapsed().as_secs_f64() as f32;
+        let elapsed = self.frame_stats.elapsed().as_secs_f64() as f32;
         let stroke_color = palette::Hsl::new_srgb(360.0 * elapsed * 0.1, 1.0, 0.7);
         let bg_color = palette::Hsv::new_srgb(180.0 + 360.0 * elapsed * 0.1, 0.4, 0.1);
         let stroke_color = Srgb::from_color(stroke_color);
@@ -209,7 +160,7 @@ impl State {
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
+                view: &frame.view,
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
@@ -260,7 +211,7 @@ impl State {
         // Submit the command in the queue to execute
         self.queue.submit([encoder.finish()]);
         self.window.pre_present_notify();
-        surface_texture.present();
+        frame.present();
     }
 }
 
@@ -296,6 +247,11 @@ impl ApplicationHandler for App {
             }
             WindowEvent::RedrawRequested => {
                 state.render();
+                // Only here; the extra renders on clicks aren't frames of the animation.
+                state.frame_stats.tick();
+                if state.frame_stats.report_due() {
+                    println!("{}", state.frame_stats);
+                }
                 // Emits a new redraw requested event.
                 state.get_window().request_redraw();
             }
@@ -311,18 +267,17 @@ impl ApplicationHandler for App {
                     && event.state == ElementState::Pressed
                 {
                     // switch paused state
-                    state.elapsed.switch_pause();
+                    state.frame_stats.switch_pause();
                 }
             }
             WindowEvent::MouseInput {
                 state: e_state,
                 button,
                 ..
+            } if e_state == ElementState::Pressed && button == MouseButton::Left => {
+                // click; update the vertex colors
+                state.render();
             }
-                if e_state == ElementState::Pressed && button == MouseButton::Left => {
-                    // click; update the vertex colors
-                    state.render();
-                }
             _ => {}
         }
     }
@@ -365,39 +320,6 @@ fn random_color() -> [f32; 3] {
     ]
 }
 
-struct PausableTimeElapse {
-    start: Option<Instant>,
-    elapsed: Duration,
-}
-
-impl PausableTimeElapse {
-    fn new() -> Self {
-        Self {
-            start: Some(Instant::now()),
-            elapsed: Duration::ZERO,
-        }
-    }
-
-    fn elapsed(&self) -> Duration {
-        match self.start {
-            Some(t) => self.elapsed + t.elapsed(),
-            None => self.elapsed,
-        }
-    }
-
-    fn switch_pause(&mut self) {
-        match self.start {
-            Some(t) => {
-                self.elapsed += t.elapsed();
-                self.start = None;
-            }
-            None => {
-                self.start = Some(Instant::now());
-            }
-        }
-    }
-}
-
 #[derive(Default, Debug)]
 struct Uniform {
     a: f32,
@@ -1,17 +1,15 @@
-use log::error;
 use std::env;
 use std::f32::consts::PI;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
 use wgpu::VertexFormat::Float32x2;
 use wgpu::util::RenderEncoder;
 use wgpu::{
-    Buffer, BufferDescriptor, BufferUsages, Color, ColorTargetState, CurrentSurfaceTexture, Device,
-    FragmentState, PrimitiveState, PrimitiveTopology, RenderPipeline,
-    RenderPipelineDescriptor, ShaderModule, VertexAttribute, VertexBufferLayout, VertexState,
-    include_wgsl,
+    Buffer, BufferDescriptor, BufferUsages, Color, ColorTargetState, Device, FragmentState,
+    PrimitiveState, PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
+    VertexAttribute, VertexBufferLayout, VertexState, include_wgsl,
 };
-use wgpu_playground::wgpu_instance_with_env_backend;
+use wgpu_playground::surface::{SrgbPolicy, SurfaceManager};
+use wgpu_playground::{AdapterSelector, FrameStats, wgpu_instance_with_env_backend};
 use winit::event::{ElementState, MouseButton};
 use winit::keyboard::{Key, NamedKey};
 use winit::{
@@ -22,13 +20,11 @@ use winit::{
 };
 
 struct State {
-    elapsed: PausableTimeElapse,
+    frame_stats: FrameStats,
     window: Arc<Window>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    size: winit::dpi::PhysicalSize<u32>,
-    surface: wgpu::Surface<'static>,
-    surface_format: wgpu::TextureFormat,
+    surface: SurfaceManager,
     module: ShaderModule,
     pipeline: RenderPipeline,
     vertex_buffer: Buffer,
@@ -40,8 +36,8 @@ impl State {
         //     wgpu::InstanceDescriptor::default().with_display_handle(Box::new(display)),
         // );
         let instance = wgpu_instance_with_env_backend();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
+        let adapter = AdapterSelector::from_env()
+            .select(&instance, None)
             .await
             .unwrap();
         let (device, queue) = adapter
@@ -52,9 +48,13 @@ impl State {
         let size = window.inner_size();
 
         let surface = instance.create_surface(window.clone()).unwrap();
-        let cap = surface.get_capabilities(&adapter);
-
-        let surface_format = cap.formats[0];
+        let surface = SurfaceManager::new(
+            surface,
+            &adapter,
+            &device,
+            (size.width, size.height),
+            SrgbPolicy::Srgb,
+        );
 
         let shader_module = device.create_shader_module(include_wgsl!("../shaders/lissajous.wgsl"));
 
@@ -84,7 +84,7 @@ impl State {
                 entry_point: None,
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: surface_format.add_srgb_suffix(),
+                    format: surface.format(),
                     blend: None,
                     write_mask: Default::default(),
                 })],
@@ -102,27 +102,19 @@ impl State {
         });
 
         let vertex_buffer = Self::create_vertex_buffer(&device, 65536 * 4);
-        let state = State {
-            elapsed: PausableTimeElapse::new(),
+        State {
+            frame_stats: FrameStats::default(),
             window,
             device,
             queue,
-            size,
             surface,
-            surface_format,
             module: shader_module,
             pipeline,
             vertex_buffer,
-        };
-
-        // Configure surface for the first time
-        state.configure_surface();
-
-        state
+        }
     }
 
     fn create_vertex_buffer(device: &Device, size: u64) -> Buffer {
-        
         device.create_buffer(&BufferDescriptor {
             label: None,
             size,
@@ -135,56 +127,14 @@ impl State {
         &self.window
     }
 
-    fn configure_surface(&self) {
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: self.surface_format,
-            // Request compatibility with the sRGB-format texture view we‘re going to create later.
-            view_formats: vec![self.surface_format.add_srgb_suffix()],
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            width: self.size.width,
-            height: self.size.height,
-            desired_maximum_frame_latency: 2,
-            present_mode: wgpu::PresentMode::AutoVsync,
-        };
-        self.surface.configure(&self.device, &surface_config);
-    }
-
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.size = new_size;
-
-        // reconfigure the surface
-        self.configure_surface();
+        self.surface.resize((new_size.width, new_size.height));
     }
 
     fn render(&mut self) {
-        // Create texture view
-        let surface_texture = match self.surface.get_current_texture() {
-            CurrentSurfaceTexture::Success(texture) => texture,
-            CurrentSurfaceTexture::Suboptimal(texture) => {
-                self.configure_surface();
-                texture
-            }
-            CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
-                return;
-            }
-            CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
-                self.configure_surface();
-                return;
-            }
-            CurrentSurfaceTexture::Validation => {
-                error!("Validation error in get_current_texture");
-                return;
-            }
+        let Some(frame) = self.surface.acquire() else {
+            return;
         };
-        let texture_view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor {
-                // Without add_srgb_suffix() the image we will be working with
-                // might not be "gamma correct".
-                format: Some(self.surface_format.add_srgb_suffix()),
-                ..Default::default()
-            });
 
         // Renders a gray screen
         let mut encoder = self.device.create_command_encoder(&Default::default());
@@ -192,7 +142,7 @@ impl State {
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
+                view: &frame.view,
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
@@ -206,7 +156,7 @@ impl State {
             multiview_mask: None,
         });
 
-        let elapsed = self.elapsed.elapsed().as_secs_f64() as f32;
+        let elapsed = self.frame_stats.elapsed().as_secs_f64() as f32;
 
         const SEGMENTS: usize = 2000;
         let mut buf = [0f32; SEGMENTS * 2];
@@ -228,7 +178,7 @@ impl State {
 
         self.window.pre_present_notify();
         self.queue.submit([encoder.finish()]);
-        surface_texture.present();
+        frame.present();
     }
 }
 
@@ -264,6 +214,11 @@ impl ApplicationHandler for App {
             }
             WindowEvent::RedrawRequested => {
                 state.render();
+                // Only here; the extra renders on clicks aren't frames of the animation.
+                state.frame_stats.tick();
+                if state.frame_stats.report_due() {
+                    println!("{}", state.frame_stats);
+                }
                 // Emits a new redraw requested event.
                 state.get_window().request_redraw();
             }
@@ -274,20 +229,19 @@ impl ApplicationHandler for App {
             }
             WindowEvent::KeyboardInput { event, .. }
                 if event.logical_key == Key::Named(NamedKey::Space)
-                    && event.state == ElementState::Pressed
-                => {
-                    // switch paused state
-                    state.elapsed.switch_pause();
-                }
+                    && event.state == ElementState::Pressed =>
+            {
+                // switch paused state
+                state.frame_stats.switch_pause();
+            }
             WindowEvent::MouseInput {
                 state: e_state,
                 button,
                 ..
+            } if e_state == ElementState::Pressed && button == MouseButton::Left => {
+                // click; update the vertex colors
+                state.render();
             }
-                if e_state == ElementState::Pressed && button == MouseButton::Left => {
-                    // click; update the vertex colors
-                    state.render();
-                }
             _ => {}
         }
     }
@@ -329,36 +283,3 @@ fn random_color() -> [f32; 3] {
         rand::random::<f32>(),
     ]
 }
-
-struct PausableTimeElapse {
-    start: Option<Instant>,
-    elapsed: Duration,
-}
-
-impl PausableTimeElapse {
-    fn new() -> Self {
-        Self {
-            start: Some(Instant::now()),
-            elapsed: Duration::ZERO,
-        }
-    }
-
-    fn elapsed(&self) -> Duration {
-        match self.start {
-            Some(t) => self.elapsed + t.elapsed(),
-            None => self.elapsed,
-        }
-    }
-
-    fn switch_pause(&mut self) {
-        match self.start {
-            Some(t) => {
-                self.elapsed += t.elapsed();
-                self.start = None;
-            }
-            None => {
-                self.start = Some(Instant::now());
-            }
-        }
-    }
-}
@@ -0,0 +1,64 @@
+//! Prints every adapter of the backends in `WGPU_BACKEND`, with its features and limits.
+//!
+//! The adapter the other binaries would pick (see [`AdapterSelector::from_env`]) is marked
+//! with `*`.
+
+use clap::Parser;
+use wgpu::{Adapter, Backends};
+use wgpu_playground::{AdapterSelector, wgpu_instance_with_env_backend};
+
+#[derive(Parser)]
+struct Args {
+    /// Only print the adapter info, without features and limits
+    #[arg(short, long)]
+    brief: bool,
+}
+
+fn print_adapter(adapter: &Adapter, selected: bool, brief: bool) {
+    let info = adapter.get_info();
+    println!(
+        "{} {} ({:?}, {:?})",
+        if selected { "*" } else { " " },
+        info.name,
+        info.backend,
+        info.device_type
+    );
+    println!(
+        "    vendor: {:#06x}, device: {:#06x}",
+        info.vendor, info.device
+    );
+    println!("    driver: {} {}", info.driver, info.driver_info);
+    if brief {
+        return;
+    }
+
+    println!("    features:");
+    for (name, _) in adapter.features().iter_names() {
+        println!("        {}", name);
+    }
+    println!("    limits: {:#?}", adapter.limits());
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let instance = wgpu_instance_with_env_backend();
+    let adapters = pollster::block_on(instance.enumerate_adapters(Backends::all()));
+    if adapters.is_empty() {
+        println!("No adapters found");
+        return Ok(());
+    }
+
+    let selected = match pollster::block_on(AdapterSelector::from_env().select(&instance, None)) {
+        Ok(adapter) => Some(adapter.get_info()),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    };
+    for adapter in &adapters {
+        let is_selected = selected.as_ref() == Some(&adapter.get_info());
+        print_adapter(adapter, is_selected, args.brief);
+    }
+    Ok(())
+}
@@ -3,12 +3,11 @@ use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
 use wgpu::{BufferDescriptor, BufferUsages};
-use wgpu_playground::wgpu_instance_with_env_backend;
+use wgpu_playground::{AdapterSelector, wgpu_instance_with_env_backend};
 
 fn main() -> anyhow::Result<()> {
     let instance = wgpu_instance_with_env_backend();
-    let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions::default());
-    let adapter = pollster::block_on(adapter)?;
+    let adapter = pollster::block_on(AdapterSelector::from_env().select(&instance, None))?;
     let x = adapter.request_device(&wgpu::DeviceDescriptor::default());
     let (device, queue) = pollster::block_on(x)?;
 
@@ -1,25 +1,43 @@
 #![feature(decl_macro)]
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use bytemuck::{cast_slice, cast_slice_mut};
-use std::process::exit;
-use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::sync::oneshot;
 use wgpu::wgt::PollType;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferBinding,
     BufferDescriptor, BufferUsages, ComputePipeline, ComputePipelineDescriptor, Device, MapMode,
-    PipelineCompilationOptions, Queue, ShaderModuleDescriptor, ShaderSource,
+    PipelineCompilationOptions, Queue, include_wgsl,
 };
-use wgpu_playground::{default, set_up_logger, wgpu_instance_with_env_backend};
-
-/// Sha256 buffer type the shader uses.
-type FatSha256Buf = [u32; SHA256_BYTES];
-
-const SHA256_BYTES: usize = 32;
-const INPUT_SIZE: usize = 32;
-/// The shader treats `u32`s as `u8`s.
-const BLOCK_BUFFER_IN_SHADER: u64 = size_of::<FatSha256Buf>() as _;
+use wgpu_playground::{AdapterSelector, default, set_up_logger, wgpu_instance_with_env_backend};
+
+const HEADER_SIZE: usize = 80;
+/// Initial SHA-256 state
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+/// Words of the shader's `Job` before `tail`
+const JOB_HEADER_WORDS: usize = 24;
+/// Index of `nonce_lo` in the shader's `Job`; `nonce_hi` follows
+const JOB_NONCE_WORD: usize = 19;
+/// Index of `nonces_left` in the shader's `Job`
+const JOB_NONCES_LEFT_WORD: usize = 23;
+/// Solutions the shader can store per dispatch
+const SOLUTION_CAPACITY: usize = 1024;
+/// The shader's `Solutions`: the count, padding and a low and high word per nonce
+const RESULT_WORDS: usize = 2 + SOLUTION_CAPACITY * 2;
+/// Candidates of `--autotune`; the dispatch size is fitted to the target latency
+const TUNE_WORKGROUP_SIZES: [u32; 6] = [32, 64, 128, 256, 512, 1024];
+const TUNE_ITERATIONS: [u32; 4] = [16, 64, 256, 1024];
+/// Most timed dispatches per candidate while fitting the dispatch size
+const TUNE_STEPS: usize = 8;
+/// Most a dispatch grows between two timed steps, so a bad estimate can't stall the GPU
+const TUNE_MAX_GROWTH: f64 = 16.0;
 
 use clap::Parser;
 use num_format::{Locale, ToFormattedString};
@@ -44,9 +62,332 @@ struct Args {
     #[arg(short, long, default_value_t = 32)]
     difficulty: u32,
 
-    /// The start hex data (in hex string).
+    /// The start hex data (in hex string). Zero-padded to `length`.
     #[arg(long)]
     start: Option<String>,
+
+    /// Length of the hashed message in bytes
+    #[arg(long, default_value_t = 32)]
+    length: usize,
+
+    /// Byte offset of the nonce in the message. The nonce is little-endian and starts at the
+    /// value found there.
+    #[arg(long, default_value_t = 0)]
+    nonce_offset: usize,
+
+    /// Width of the nonce in bytes (1 to 8)
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(1..=8))]
+    nonce_width: u8,
+
+    /// Mine a Bitcoin block header instead: a file with the 80-byte header, as hex or raw
+    /// bytes. Its nonce is swept from the given value, against the target in its bits field.
+    #[arg(long, conflicts_with_all = ["start", "difficulty", "length", "nonce_offset", "nonce_width"])]
+    header: Option<PathBuf>,
+
+    /// Stop after this many solutions
+    #[arg(short, long, default_value_t = 1)]
+    count: u64,
+
+    /// Keep mining until the nonces run out
+    #[arg(long, conflicts_with = "count")]
+    forever: bool,
+
+    /// Save the progress to this file, and resume from it if it exists
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoint saves
+    #[arg(long, default_value_t = 10)]
+    checkpoint_interval: u64,
+
+    /// Measure the hashrate of workgroup sizes, dispatch sizes and iterations within the
+    /// device limits and mine with the best. The result is cached per adapter.
+    #[arg(long, conflicts_with_all = ["workgroup_size", "dispatch_x", "iterations"])]
+    autotune: bool,
+
+    /// Wall time of one dispatch that `--autotune` aims for, in milliseconds. Long dispatches
+    /// make the display stutter and may get the GPU reset.
+    #[arg(long, default_value_t = 100)]
+    target_latency: u64,
+
+    /// Where `--autotune` caches its results
+    #[arg(long, default_value = "sha256-miner-tuning.json")]
+    tuning_cache: PathBuf,
+
+    /// Tune again even if the cache has parameters for this adapter
+    #[arg(long, requires = "autotune")]
+    retune: bool,
+}
+
+/// Launch parameters of the shader
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct Tuning {
+    workgroup_size: u32,
+    dispatch_x: u32,
+    iterations: u32,
+}
+
+impl Tuning {
+    fn from_args(args: &Args) -> anyhow::Result<Self> {
+        let tuning = Self {
+            workgroup_size: args.workgroup_size,
+            dispatch_x: args.dispatch_x,
+            iterations: args.iterations,
+        };
+        // the shader's nonce offsets are u32
+        if tuning.hashes_per_dispatch() > 1 << 32 {
+            bail!(
+                "workgroup size × dispatch x × iterations must be at most 2^32, got {}",
+                tuning.hashes_per_dispatch()
+            );
+        }
+        Ok(tuning)
+    }
+
+    fn hashes_per_dispatch(&self) -> u64 {
+        self.workgroup_size as u64 * self.dispatch_x as u64 * self.iterations as u64
+    }
+}
+
+/// An `--autotune` result in the tuning cache, which maps adapter names to these
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedTuning {
+    /// `--target-latency` it was tuned for
+    target_latency: u64,
+    #[serde(flatten)]
+    tuning: Tuning,
+    /// Measured, in H/s
+    hashrate: f64,
+}
+
+/// A solution, printed as a JSON line
+#[derive(Serialize)]
+struct Solution {
+    nonce: u64,
+    /// The message with the nonce, in hex
+    input: String,
+    /// The hash as compared with the target, in hex
+    hash: String,
+    /// Index of the dispatch that found it
+    dispatch: usize,
+    /// Seconds since mining started
+    elapsed: f64,
+}
+
+/// Progress of a search, saved as JSON by `--checkpoint`
+#[derive(Serialize, Deserialize, Debug)]
+struct Checkpoint {
+    /// The message with the first nonce, in hex
+    message: String,
+    nonce_offset: usize,
+    nonce_width: usize,
+    /// In hex
+    target: String,
+    double_hash: bool,
+    hash_le: bool,
+    /// Informational; resuming with other values is fine
+    #[serde(flatten)]
+    tuning: Tuning,
+    /// Dispatches done
+    dispatch: usize,
+    /// Nonces swept from the first one
+    hashes: u64,
+    /// Solutions printed
+    found: u64,
+    /// Seconds spent mining
+    elapsed: f64,
+}
+
+impl Checkpoint {
+    /// A checkpoint at the start of `job`
+    fn new(job: &Job, tuning: Tuning) -> Self {
+        Self {
+            message: hex::encode(&job.message),
+            nonce_offset: job.nonce_offset,
+            nonce_width: job.nonce_width,
+            target: hex::encode(job.target),
+            double_hash: job.double_hash,
+            hash_le: job.hash_le,
+            tuning,
+            dispatch: 0,
+            hashes: 0,
+            found: 0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Loads the checkpoint at `path`, or starts a new one if there is no file.
+    fn load_or_new(path: &Path, job: &Job, tuning: Tuning) -> anyhow::Result<Self> {
+        let new = Self::new(job, tuning);
+        let content = match fs::read(path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(new),
+            Err(e) => return Err(e.into()),
+        };
+        let loaded: Self = serde_json::from_slice(&content)?;
+        if !loaded.same_job(&new) {
+            bail!(
+                "The checkpoint {} is for another search; remove it or pass the same arguments",
+                path.display()
+            );
+        }
+        Ok(loaded)
+    }
+
+    fn same_job(&self, other: &Self) -> bool {
+        self.message == other.message
+            && self.nonce_offset == other.nonce_offset
+            && self.nonce_width == other.nonce_width
+            && self.target == other.target
+            && self.double_hash == other.double_hash
+            && self.hash_le == other.hash_le
+    }
+
+    /// Writes to a temporary file first so a crash never leaves a truncated checkpoint.
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
+
+/// A search: vary the nonce in `message` until its hash is at most `target`.
+struct Job {
+    message: Vec<u8>,
+    nonce_offset: usize,
+    nonce_width: usize,
+    /// Big-endian
+    target: [u8; 32],
+    /// Hash the digest again, like Bitcoin
+    double_hash: bool,
+    /// Compare the hash as a little-endian number, like Bitcoin
+    hash_le: bool,
+}
+
+impl Job {
+    fn from_args(args: &Args) -> anyhow::Result<Self> {
+        let start = hex::decode(args.start.as_deref().unwrap_or_default())?;
+        if start.len() > args.length {
+            bail!("Length of `start` must be <= {}", args.length);
+        }
+        let nonce_width = args.nonce_width as usize;
+        if args.nonce_offset + nonce_width > args.length {
+            bail!("The nonce must lie within the message");
+        }
+        if args.difficulty > 256 {
+            bail!("Difficulty must be at most 256 bits");
+        }
+        let mut message = vec![0_u8; args.length];
+        message[..start.len()].copy_from_slice(&start);
+        Ok(Self {
+            message,
+            nonce_offset: args.nonce_offset,
+            nonce_width,
+            target: target_from_difficulty(args.difficulty),
+            double_hash: false,
+            hash_le: false,
+        })
+    }
+
+    fn from_header(path: &Path) -> anyhow::Result<Self> {
+        let header = read_header(path)?;
+        let bits = u32::from_le_bytes(header[72..76].try_into().unwrap());
+        Ok(Self {
+            message: header.into(),
+            nonce_offset: 76,
+            nonce_width: 4,
+            target: target_from_bits(bits)?,
+            double_hash: true,
+            hash_le: true,
+        })
+    }
+
+    /// The nonce the message starts with
+    fn first_nonce(&self) -> u64 {
+        let mut bytes = [0_u8; 8];
+        bytes[..self.nonce_width]
+            .copy_from_slice(&self.message[self.nonce_offset..][..self.nonce_width]);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Number of distinct nonces, saturated
+    fn nonce_count(&self) -> u64 {
+        1_u64
+            .checked_shl(self.nonce_width as u32 * 8)
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Drops the bits of `nonce` that don't fit in the nonce field.
+    fn wrap_nonce(&self, nonce: u64) -> u64 {
+        if self.nonce_width == 8 {
+            nonce
+        } else {
+            nonce % self.nonce_count()
+        }
+    }
+
+    fn message_with_nonce(&self, nonce: u64) -> Vec<u8> {
+        let mut message = self.message.clone();
+        message[self.nonce_offset..][..self.nonce_width]
+            .copy_from_slice(&nonce.to_le_bytes()[..self.nonce_width]);
+        message
+    }
+
+    /// The hash of `message` as it is compared with the target
+    fn hash(&self, message: &[u8]) -> [u8; 32] {
+        let mut hash: [u8; 32] = sha2::Sha256::digest(message).into();
+        if self.double_hash {
+            hash = sha2::Sha256::digest(hash).into();
+        }
+        if self.hash_le {
+            hash.reverse();
+        }
+        hash
+    }
+
+    /// The shader's `Job`, without the dispatch's first nonce.
+    ///
+    /// The blocks before the one holding the nonce are the same for every nonce, so they are
+    /// hashed here and the shader starts from the resulting midstate.
+    fn shader_input(&self) -> Vec<u32> {
+        // SHA-256 padding: 0x80, zeros, then the length in bits
+        let mut padded = self.message.clone();
+        padded.push(0x80);
+        padded.resize((self.message.len() + 1 + 8).next_multiple_of(64) - 8, 0);
+        padded.extend_from_slice(&(self.message.len() as u64 * 8).to_be_bytes());
+        // the shader ORs the nonce in
+        padded[self.nonce_offset..][..self.nonce_width].fill(0);
+
+        let midstate_blocks = self.nonce_offset / 64;
+        let (prefix, tail) = padded.split_at(midstate_blocks * 64);
+        let mut midstate = SHA256_IV;
+        let prefix_blocks = prefix
+            .chunks(64)
+            .map(|x| x.try_into().unwrap())
+            .collect::<Vec<_>>();
+        sha2::block_api::compress256(&mut midstate, &prefix_blocks);
+
+        let mut words = Vec::with_capacity(JOB_HEADER_WORDS + tail.len() / 4);
+        words.extend_from_slice(&midstate);
+        words.extend(be_words(&self.target));
+        words.extend([
+            (tail.len() / 64) as u32,
+            (self.nonce_offset - prefix.len()) as u32,
+            self.nonce_width as u32,
+            // nonce, set per dispatch
+            0,
+            0,
+            self.double_hash.into(),
+            self.hash_le.into(),
+            // nonces left, set per dispatch
+            0,
+        ]);
+        assert_eq!(words.len(), JOB_HEADER_WORDS);
+        words.extend(be_words(tail));
+        words
+    }
 }
 
 struct State {
@@ -60,15 +401,16 @@ struct State {
 }
 
 impl State {
-    async fn new(args: &Args) -> anyhow::Result<Self> {
-        let instance = wgpu_instance_with_env_backend();
-        let adapter = instance.request_adapter(&default!()).await?;
-        let (device, queue) = adapter.request_device(&default!()).await?;
-
-        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
-            label: None,
-            source: ShaderSource::Wgsl(wgsl_source(args.difficulty).into()),
-        });
+    /// `input_words`: size of the job given to the shader
+    fn new(
+        device: &Device,
+        queue: &Queue,
+        workgroup_size: u32,
+        iterations: u32,
+        input_words: usize,
+    ) -> Self {
+        let shader_module =
+            device.create_shader_module(include_wgsl!("../shaders/sha256-miner.wgsl"));
         let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
             label: None,
             layout: None,
@@ -76,13 +418,8 @@ impl State {
             entry_point: None,
             compilation_options: PipelineCompilationOptions {
                 constants: &[
-                    ("WORKGROUP_SIZE", args.workgroup_size as f64),
-                    ("ITERATIONS_PER_THREAD", args.iterations as f64),
-                    (
-                        "RUNS_PER_DISPATCH",
-                        (args.dispatch_x * args.workgroup_size) as f64,
-                    ),
-                    ("DIFFICULTY_BITS", args.difficulty as f64),
+                    ("WORKGROUP_SIZE", workgroup_size as f64),
+                    ("ITERATIONS_PER_THREAD", iterations as f64),
                 ],
                 zero_initialize_workgroup_memory: false,
             },
@@ -91,19 +428,19 @@ impl State {
 
         let input_buffer = device.create_buffer(&BufferDescriptor {
             label: None,
-            size: INPUT_SIZE as u64 * 4,
+            size: input_words as u64 * 4,
             usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         let result_buffer = device.create_buffer(&BufferDescriptor {
             label: None,
-            size: BLOCK_BUFFER_IN_SHADER,
+            size: RESULT_WORDS as u64 * 4,
             usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
         let map_read_buffer = device.create_buffer(&BufferDescriptor {
             label: None,
-            size: BLOCK_BUFFER_IN_SHADER,
+            size: RESULT_WORDS as u64 * 4,
             usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -131,28 +468,26 @@ impl State {
             ],
         });
 
-        Ok(Self {
-            queue,
-            device,
+        Self {
+            queue: queue.clone(),
+            device: device.clone(),
             pipeline,
             input_buffer,
             bind_group,
             result_buffer,
             map_read_buffer,
-        })
+        }
     }
 
-    fn write_input_data(&self, buf: &[u8]) {
-        let mut input_data = [0_u32; INPUT_SIZE];
-        for (i, &b) in buf.iter().enumerate() {
-            input_data[i] = b as _;
-        }
+    fn write_input_words(&self, words: &[u32]) {
         self.queue
-            .write_buffer(&self.input_buffer, 0, cast_slice(&input_data));
+            .write_buffer(&self.input_buffer, 0, cast_slice(words));
     }
 
     fn compute_dispatch(&self, workgroups_x: u32) {
         let mut encoder = self.device.create_command_encoder(&default!());
+        // reset the solution count
+        encoder.clear_buffer(&self.result_buffer, 0, Some(4));
 
         let mut pass = encoder.begin_compute_pass(&default!());
         pass.set_pipeline(&self.pipeline);
@@ -182,90 +517,199 @@ impl State {
         self.map_read_buffer.unmap();
         Ok(())
     }
-}
-
-fn add_big_int(data: &mut [u8; 32], n: u32) {
-    let mut carry = n;
-
-    for byte in data.iter_mut() {
-        if carry == 0 {
-            break;
-        }
 
-        // 将当前字节与进位相加
-        // 先转为 u32 避免计算过程中溢出
-        let sum = *byte as u32 + carry;
-
-        // 取低 8 位存回
-        *byte = (sum & 0xFF) as u8;
-
-        // 计算新的进位
-        carry = sum >> 8;
+    /// Runs a dispatch to completion and returns its wall time.
+    async fn time_dispatch(
+        &self,
+        workgroups_x: u32,
+        result: &mut [u32],
+    ) -> anyhow::Result<Duration> {
+        let start = Instant::now();
+        self.compute_dispatch(workgroups_x);
+        self.read_result(cast_slice_mut(result)).await?;
+        Ok(start.elapsed())
     }
 }
 
-#[inline(always)]
-fn convert_fat_buf(buf: &FatSha256Buf) -> [u8; SHA256_BYTES] {
-    buf.map(|x| x as u8)
+/// Finds the tuning with the highest hashrate on `job_words`, a job from `Job::shader_input`.
+///
+/// For each workgroup size and iteration count, the dispatch is grown until it takes about
+/// `target_latency`.
+async fn autotune(
+    device: &Device,
+    queue: &Queue,
+    job_words: &[u32],
+    target_latency: Duration,
+) -> anyhow::Result<CachedTuning> {
+    let limits = device.limits();
+    let max_workgroup_size = limits
+        .max_compute_workgroup_size_x
+        .min(limits.max_compute_invocations_per_workgroup);
+    let mut input = job_words.to_vec();
+    // solutions found while tuning are ignored
+    input[JOB_NONCES_LEFT_WORD] = u32::MAX;
+    let mut result = vec![0_u32; RESULT_WORDS];
+
+    let mut best: Option<CachedTuning> = None;
+    for workgroup_size in TUNE_WORKGROUP_SIZES
+        .into_iter()
+        .filter(|&x| x <= max_workgroup_size)
+    {
+        for iterations in TUNE_ITERATIONS {
+            let state = State::new(device, queue, workgroup_size, iterations, input.len());
+            state.write_input_words(&input);
+            // the shader's nonce offsets are u32
+            let max_dispatch_x = limits
+                .max_compute_workgroups_per_dimension
+                .min(u32::MAX / (workgroup_size * iterations));
+
+            // warm-up
+            state.time_dispatch(1, &mut result).await?;
+            let mut dispatch_x = 1;
+            let mut elapsed = state.time_dispatch(dispatch_x, &mut result).await?;
+            for _ in 0..TUNE_STEPS {
+                let scale = target_latency.as_secs_f64() / elapsed.as_secs_f64().max(1e-6);
+                if (0.8..1.25).contains(&scale) {
+                    break;
+                }
+                let next = (dispatch_x as f64 * scale.min(TUNE_MAX_GROWTH)) as u32;
+                let next = next.clamp(1, max_dispatch_x);
+                if next == dispatch_x {
+                    break;
+                }
+                dispatch_x = next;
+                elapsed = state.time_dispatch(dispatch_x, &mut result).await?;
+            }
+
+            let tuning = Tuning {
+                workgroup_size,
+                dispatch_x,
+                iterations,
+            };
+            let hashrate = tuning.hashes_per_dispatch() as f64 / elapsed.as_secs_f64();
+            eprintln!(
+                "{:?}: {:?} per dispatch, {} H/s",
+                tuning,
+                elapsed,
+                (hashrate.round() as u64).to_formatted_string(&Locale::en)
+            );
+            if elapsed > target_latency * 2 {
+                // too slow even at the smallest dispatch
+                continue;
+            }
+            if best.as_ref().is_none_or(|x| hashrate > x.hashrate) {
+                best = Some(CachedTuning {
+                    target_latency: target_latency.as_millis() as u64,
+                    tuning,
+                    hashrate,
+                });
+            }
+        }
+    }
+    best.ok_or_else(|| anyhow!("No tuning meets the target latency of {:?}", target_latency))
 }
 
-fn generate_check_difficulty_wgsl(difficulty_bits: u32) -> String {
-    let mut conditions = Vec::new();
-
-    // 处理完整的字节 (8 bits 每组)
-    let full_bytes = difficulty_bits / 8;
-    for i in 0..full_bytes {
-        conditions.push(format!("buf[{}] == 0u", i));
+/// The tuning for the adapter named `adapter`: from the cache, or measured and then cached.
+async fn cached_autotune(
+    args: &Args,
+    adapter: &str,
+    device: &Device,
+    queue: &Queue,
+    job_words: &[u32],
+) -> anyhow::Result<Tuning> {
+    let path = &args.tuning_cache;
+    let mut cache: BTreeMap<String, CachedTuning> = match fs::read(path) {
+        Ok(x) => serde_json::from_slice(&x)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => default!(),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(cached) = cache.get(adapter)
+        && cached.target_latency == args.target_latency
+        && !args.retune
+    {
+        eprintln!("Using the tuning cached in {}", path.display());
+        return Ok(cached.tuning);
     }
 
-    // 处理剩余的位 (非 8 整除的部分)
-    let remaining_bits = difficulty_bits % 8;
-    if remaining_bits > 0 {
-        let shift = 8 - remaining_bits;
-        // 使用索引 full_bytes 指向下一个字节
-        conditions.push(format!("(buf[{}] >> {}u) == 0u", full_bytes, shift));
-    }
+    eprintln!("Tuning for {}", adapter);
+    let tuned = autotune(
+        device,
+        queue,
+        job_words,
+        Duration::from_millis(args.target_latency),
+    )
+    .await?;
+    let tuning = tuned.tuning;
+    cache.insert(adapter.into(), tuned);
+    fs::write(path, serde_json::to_string_pretty(&cache)?)?;
+    Ok(tuning)
+}
 
-    // 处理难度为 0 的特殊情况
-    let final_condition = if conditions.is_empty() {
-        "true".to_string()
+/// Reads a block header file: hex text (e.g. from `bitcoin-cli getblockheader <hash> false`)
+/// or the raw 80 bytes.
+fn read_header(path: &Path) -> anyhow::Result<[u8; HEADER_SIZE]> {
+    let content = fs::read(path)?;
+    let bytes = if content.len() == HEADER_SIZE {
+        content
     } else {
-        conditions.join(" && ")
+        hex::decode(String::from_utf8(content)?.trim())?
     };
+    bytes
+        .try_into()
+        .map_err(|x: Vec<u8>| anyhow!("A block header has {} bytes, got {}", HEADER_SIZE, x.len()))
+}
 
-    format!(
-        r#"
-fn check_difficulty(buf: ptr<function, array<u32, SHA256_BLOCK_SIZE>>) -> bool {{
-    return {};
-}}
-"#,
-        final_condition
-    )
+/// Expands the compact `bits` field of a header into the 256-bit target, big-endian.
+fn target_from_bits(bits: u32) -> anyhow::Result<[u8; 32]> {
+    if bits & 0x0080_0000 != 0 {
+        bail!("Negative target in bits {:#010x}", bits);
+    }
+    // target = mantissa * 256^(exponent - 3)
+    let exponent = (bits >> 24) as i32;
+    let mut target = [0_u8; 32];
+    for (i, &byte) in bits.to_be_bytes()[1..].iter().enumerate() {
+        let power = exponent - 1 - i as i32;
+        if power < 0 {
+            // shifted out to the right
+            continue;
+        }
+        if power >= 32 {
+            if byte != 0 {
+                bail!("Target of bits {:#010x} exceeds 256 bits", bits);
+            }
+            continue;
+        }
+        target[31 - power as usize] = byte;
+    }
+    Ok(target)
 }
 
-fn wgsl_source(difficulty_bits: u32) -> String {
-    let mut source = include_str!("../shaders/sha256-miner.wgsl")
-        .lines()
-        .collect::<Vec<_>>();
-    source.remove(0);
-    let generated = generate_check_difficulty_wgsl(difficulty_bits);
-    for x in generated.lines().rev() {
-        source.insert(0, x);
+/// The largest hash with `difficulty_bits` leading zero bits, big-endian.
+fn target_from_difficulty(difficulty_bits: u32) -> [u8; 32] {
+    let mut target = [0xff_u8; 32];
+    for (i, byte) in target.iter_mut().enumerate() {
+        let zero_bits = difficulty_bits.saturating_sub(i as u32 * 8).min(8);
+        *byte = (0xff_u16 >> zero_bits) as u8;
     }
-    source.join("\n")
+    target
+}
+
+/// Splits `bytes` into big-endian words.
+fn be_words(bytes: &[u8]) -> impl Iterator<Item = u32> {
+    bytes
+        .chunks(4)
+        .map(|x| u32::from_be_bytes(x.try_into().unwrap()))
 }
 
-fn print_result_and_exit(buf: FatSha256Buf, start: Instant) {
-    use sha2::Digest;
-    let mut hasher = sha2::Sha256::new();
-    hasher.update(convert_fat_buf(&buf));
-    let hash = hex::encode(hasher.finalize());
-
-    println!("Result:");
-    println!("  input: {}", hex::encode(convert_fat_buf(&buf)));
-    println!("  sha256: {}", hash);
-    println!("  elapsed: {:?}", start.elapsed());
-    exit(0);
+fn print_progress(counter: usize, position: &str, elapsed: Duration, hashes: u64) {
+    eprintln!(
+        "dispatch: {}, start: {}, elapsed: {:?}, hashes: {}, hashrate: {} H/s",
+        counter,
+        position,
+        elapsed,
+        hashes.to_formatted_string(&Locale::en),
+        ((hashes as f64 / elapsed.as_secs_f64()).round() as u64).to_formatted_string(&Locale::en)
+    );
 }
 
 #[tokio::main]
@@ -273,41 +717,123 @@ async fn main() -> anyhow::Result<()> {
     set_up_logger();
 
     let args = Args::parse();
-    let runs_per_dispatch = args.dispatch_x * args.workgroup_size;
 
-    println!("Args: {:?}", args);
+    // stdout only gets the solutions
+    eprintln!("Args: {:?}", args);
 
-    let arg_start = hex::decode(args.start.as_deref().unwrap_or_default())?;
-    if arg_start.len() > 32 {
-        return Err(anyhow!("Length of `start` must be <= 32"));
-    }
+    let job = match &args.header {
+        Some(path) => Job::from_header(path)?,
+        None => Job::from_args(&args)?,
+    };
+    eprintln!("Target: {}", hex::encode(job.target));
+    let wanted = if args.forever { u64::MAX } else { args.count };
 
-    let state = State::new(&args).await?;
-    let mut input_data = [0_u8; INPUT_SIZE];
-    input_data[..arg_start.len()].copy_from_slice(&arg_start);
-    let mut result = [0_u32; SHA256_BYTES];
-    let mut counter = 0_usize;
+    let mut input = job.shader_input();
+
+    let instance = wgpu_instance_with_env_backend();
+    let adapter = AdapterSelector::from_env().select(&instance, None).await?;
+    let (device, queue) = adapter.request_device(&default!()).await?;
+    let tuning = if args.autotune {
+        cached_autotune(&args, &adapter.get_info().name, &device, &queue, &input).await?
+    } else {
+        Tuning::from_args(&args)?
+    };
+    eprintln!("Tuning: {:?}", tuning);
+    let state = State::new(
+        &device,
+        &queue,
+        tuning.workgroup_size,
+        tuning.iterations,
+        input.len(),
+    );
+    let first_nonce = job.first_nonce();
+    let mut result = vec![0_u32; RESULT_WORDS];
+
+    let mut checkpoint = match &args.checkpoint {
+        Some(path) => Checkpoint::load_or_new(path, &job, tuning)?,
+        None => Checkpoint::new(&job, tuning),
+    };
+    if checkpoint.hashes != 0 {
+        eprintln!(
+            "Resuming after {} hashes and {} solutions",
+            checkpoint.hashes.to_formatted_string(&Locale::en),
+            checkpoint.found
+        );
+    }
+    let mut counter = checkpoint.dispatch;
+    let mut hashes = checkpoint.hashes;
+    let mut found = checkpoint.found;
+    let elapsed_before = Duration::from_secs_f64(checkpoint.elapsed);
     let start = Instant::now();
-    let mut hashes = 0_u64;
-    loop {
-        println!(
-            "dispatch: {}, start: {}, elapsed: {:?}, hashes: {}, hashrate: {} H/s",
+    let mut saved_at = start;
+
+    while found < wanted && hashes < job.nonce_count() {
+        let nonce = job.wrap_nonce(first_nonce.wrapping_add(hashes));
+        print_progress(
             counter,
-            hex::encode(input_data),
-            start.elapsed(),
-            hashes.to_formatted_string(&Locale::en),
-            ((hashes as f64 / start.elapsed().as_secs_f64()).round() as u64)
-                .to_formatted_string(&Locale::en)
+            &format!("nonce {}", nonce),
+            elapsed_before + start.elapsed(),
+            hashes,
         );
-        state.write_input_data(&input_data);
-        state.compute_dispatch(args.dispatch_x);
-        let hashes_computed = runs_per_dispatch * args.iterations;
-        hashes += hashes_computed as u64;
-        add_big_int(&mut input_data, hashes_computed);
+        input[JOB_NONCE_WORD] = nonce as u32;
+        input[JOB_NONCE_WORD + 1] = (nonce >> 32) as u32;
+        // don't wrap around to nonces swept already
+        input[JOB_NONCES_LEFT_WORD] = (job.nonce_count() - hashes).min(u32::MAX as u64) as u32;
+        state.write_input_words(&input);
+        state.compute_dispatch(tuning.dispatch_x);
+        // the last dispatch stops at the end of the nonces
+        hashes = (hashes + tuning.hashes_per_dispatch()).min(job.nonce_count());
         state.read_result(cast_slice_mut(&mut result)).await?;
-        if result.iter().any(|x| *x != 0) {
-            print_result_and_exit(result, start);
+
+        let count = result[0] as usize;
+        if count > SOLUTION_CAPACITY {
+            eprintln!(
+                "{} solutions in dispatch {}; only the first {} are kept",
+                count, counter, SOLUTION_CAPACITY
+            );
+        }
+        let mut nonces = result[2..][..count.min(SOLUTION_CAPACITY) * 2]
+            .chunks(2)
+            .map(|x| job.wrap_nonce(x[0] as u64 | (x[1] as u64) << 32))
+            .collect::<Vec<_>>();
+        nonces.sort_unstable();
+        for nonce in nonces.into_iter().take((wanted - found) as usize) {
+            let input = job.message_with_nonce(nonce);
+            let hash = job.hash(&input);
+            if hash > job.target {
+                bail!(
+                    "The GPU reported nonce {} whose hash {} misses the target",
+                    nonce,
+                    hex::encode(hash)
+                );
+            }
+            let solution = Solution {
+                nonce,
+                input: hex::encode(input),
+                hash: hex::encode(hash),
+                dispatch: counter,
+                elapsed: (elapsed_before + start.elapsed()).as_secs_f64(),
+            };
+            println!("{}", serde_json::to_string(&solution)?);
+            found += 1;
         }
         counter += 1;
+
+        if let Some(path) = &args.checkpoint {
+            let done = found >= wanted || hashes >= job.nonce_count();
+            if done || saved_at.elapsed() >= Duration::from_secs(args.checkpoint_interval) {
+                checkpoint.tuning = tuning;
+                checkpoint.dispatch = counter;
+                checkpoint.hashes = hashes;
+                checkpoint.found = found;
+                checkpoint.elapsed = (elapsed_before + start.elapsed()).as_secs_f64();
+                checkpoint.save(path)?;
+                saved_at = Instant::now();
+            }
+        }
+    }
+    if found < wanted && !args.forever {
+        eprintln!("Swept all nonces; found {} of {} solutions", found, wanted);
     }
+    Ok(())
 }
@@ -1,25 +1,39 @@
+#![feature(try_blocks)]
+
+use clap::Parser;
 use std::env;
 use std::sync::Arc;
-use std::time::Instant;
+use wgpu_playground::surface::{PresentArgs, PresentConfig};
 use wgpu_playground::triangle_rotation::State;
-use wgpu_playground::{WgpuStateInitInfo, wgpu_instance_with_env_backend};
+use wgpu_playground::{FrameStats, WgpuStateInitInfo, wgpu_instance_with_env_backend};
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::window::{Window, WindowId};
 
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    present: PresentArgs,
+}
+
 struct App {
     state: Option<State>,
     window: Option<Arc<Window>>,
-    start: Instant,
+    frame_stats: FrameStats,
+    present: PresentConfig,
+    /// Why the window couldn't be set up; returned from `main`
+    error: Option<anyhow::Error>,
 }
 
 impl App {
-    fn new() -> Self {
+    fn new(present: PresentConfig) -> Self {
         Self {
-            start: Instant::now(),
+            frame_stats: FrameStats::default(),
+            present,
             window: None,
             state: None,
+            error: None,
         }
     }
 }
@@ -33,26 +47,38 @@ impl ApplicationHandler for App {
                 .unwrap(),
         );
 
-        let wgpu_instance = wgpu_instance_with_env_backend();
-        let surface = wgpu_instance.create_surface(Arc::clone(&window)).unwrap();
-        let size = window.inner_size();
-
-        pollster::block_on(async {
-            let state = State::new(WgpuStateInitInfo {
-                instance: wgpu_instance,
-                surface,
-                size: size.into(),
-            })
-            .await;
-            self.state = Some(state);
+        let result = pollster::block_on(async {
+            let result: anyhow::Result<()> = try {
+                let wgpu_instance = wgpu_instance_with_env_backend();
+                let surface = wgpu_instance
+                    .create_surface(Arc::clone(&window))
+                    .map_err(anyhow::Error::msg)?;
+                let size = window.inner_size();
+                let state = State::new(WgpuStateInitInfo {
+                    instance: wgpu_instance,
+                    surface,
+                    size: size.into(),
+                    present: self.present,
+                })
+                .await?;
+                self.state = Some(state);
+            };
+            result
         });
+        if let Err(e) = result {
+            self.error = Some(e);
+            event_loop.exit();
+            return;
+        }
 
         window.request_redraw();
         self.window = Some(window);
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
-        let state = self.state.as_mut().unwrap();
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
@@ -60,10 +86,14 @@ impl ApplicationHandler for App {
             }
             WindowEvent::RedrawRequested => {
                 if let Some(w) = &self.window {
-                    state.update_elapsed(self.start.elapsed().as_secs_f32());
+                    state.update_elapsed(self.frame_stats.elapsed().as_secs_f32());
                     state.render(|| {
                         w.pre_present_notify();
                     });
+                    self.frame_stats.tick();
+                    if self.frame_stats.report_due() {
+                        println!("{}", self.frame_stats);
+                    }
                     w.request_redraw();
                 }
             }
@@ -86,7 +116,9 @@ impl ApplicationHandler for App {
     }
 }
 
-fn main() {
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
     // wgpu uses `log` for all of our logging, so we initialize a logger with the `env_logger` crate.
     //
     // To change the log level, set the `RUST_LOG` environment variable. See the `env_logger`
@@ -110,6 +142,10 @@ fn main() {
     // the background.
     // event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = App::new();
+    let mut app = App::new((&args.present).into());
     event_loop.run_app(&mut app).unwrap();
+    match app.error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
@@ -4,15 +4,30 @@
 //!
 //! At 1024x1024 surface dimension, DX12 on Windows 10 has ~5 fps higher than
 //! Vulkan on Windows 10 & Vulkan on Linux. Test hardware: NVIDIA GeForce RTX 3060 Mobile / Max-Q.
+//!
+//! Drag with the left mouse button to orbit, scroll to zoom, and press Space to toggle
+//! the auto-rotation. Keys `1`-`0` decrease/increase, in pairs: power, bailout, step size,
+//! max steps and refinement iterations.
 
-use chrono::Local;
 use clap::Parser;
+use log::info;
+use serde::Serialize;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use wgpu_playground::vsbm::{Config, State};
-use wgpu_playground::{WgpuStateInitInfo, default, wgpu_instance_with_env_backend};
+use std::time::{Duration, Instant};
+use wgpu::Backends;
+use wgpu_playground::surface::{PresentArgs, PresentConfig};
+use wgpu_playground::vsbm::{Config, FractalParams, State};
+use wgpu_playground::{
+    AdapterSelector, FrameStats, WgpuStateInitInfo, default, wgpu_instance_with_backends,
+    wgpu_instance_with_env_backend,
+};
 use winit::application::ApplicationHandler;
+use winit::dpi::PhysicalSize;
 use winit::event_loop::ActiveEventLoop;
+use winit::keyboard::{Key, NamedKey};
 use winit::window::{Window, WindowId};
 use winit::{
     event::*,
@@ -23,14 +38,58 @@ use winit::{
 struct Args {
     #[arg(short = 'i', long, default_value = "5")]
     kernel_iterations: u32,
+    /// Power of the Mandelbulb formula, at least 1
+    #[arg(long, default_value = "8.0")]
+    power: f32,
+    #[arg(long, default_value = "6.0")]
+    bailout: f32,
+    /// Ray-march step size, relative to the camera distance
+    #[arg(long, default_value = "0.002")]
+    step_size: f32,
+    /// Maximum ray-march steps per pixel
+    #[arg(long, default_value = "1000")]
+    max_steps: u32,
+    /// Iterations used to refine a surface hit
+    #[arg(long, default_value = "8")]
+    refine_iterations: u32,
+    /// Render frames offscreen and write them as numbered PNGs into this directory
+    /// instead of opening a window.
+    #[arg(long)]
+    record: Option<PathBuf>,
+    /// Render a fixed camera path offscreen on every available backend and print
+    /// a JSON summary of the frame times.
+    #[arg(long, conflicts_with = "record")]
+    bench: bool,
+    /// Number of frames to record or benchmark
+    #[arg(long, default_value = "100")]
+    frames: u32,
+    #[command(flatten)]
+    present: PresentArgs,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchSummary {
+    backend: String,
+    adapter: String,
+    /// `gpu` for timestamp queries, `cpu` for submit-to-idle wall time
+    timing: &'static str,
+    frames: usize,
+    min_ms: f64,
+    avg_ms: f64,
+    p99_ms: f64,
 }
 
 #[derive(Default)]
 struct App {
     pub state: Option<State>,
     pub window: Option<Arc<Window>>,
-    pub last_frame_time: u64,
+    pub frame_stats: FrameStats,
     pub animation_config: Config,
+    pub present: PresentConfig,
+    pub dragging: bool,
+    pub cursor_position: Option<(f64, f64)>,
+    /// Why the window couldn't be set up; returned from `main`
+    pub error: Option<anyhow::Error>,
 }
 
 impl ApplicationHandler for App {
@@ -38,14 +97,15 @@ impl ApplicationHandler for App {
         // Create window object
         let window = Arc::new(
             event_loop
-                .create_window(Window::default_attributes())
+                .create_window(
+                    Window::default_attributes().with_inner_size(PhysicalSize::new(1024, 1024)),
+                )
                 .unwrap(),
         );
 
-        pollster::block_on(async {
+        let result = pollster::block_on(async {
             let result: anyhow::Result<()> = try {
-                // let size = window.inner_size();
-                let size = (1024, 1024);
+                let size = window.inner_size();
                 let instance = wgpu_instance_with_env_backend();
                 let surface = instance
                     .create_surface(Arc::clone(&window))
@@ -53,19 +113,22 @@ impl ApplicationHandler for App {
                 let state = State::new(
                     WgpuStateInitInfo {
                         instance,
-                        size,
+                        size: (size.width, size.height),
                         surface,
+                        present: self.present,
                     },
-                    Config {
-                        kernel_iterations: self.animation_config.kernel_iterations,
-                    },
+                    self.animation_config,
                 )
-                .await;
+                .await?;
                 self.state = Some(state);
             };
             result
-        })
-        .unwrap();
+        });
+        if let Err(e) = result {
+            self.error = Some(e);
+            event_loop.exit();
+            return;
+        }
 
         window.request_redraw();
         self.window = Some(window);
@@ -77,10 +140,51 @@ impl ApplicationHandler for App {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
-        let state = self.state.as_mut().unwrap();
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::Resized(_physical_size) => state.resize((1024, 1024)),
+            WindowEvent::Resized(size) => state.resize((size.width, size.height)),
+            WindowEvent::MouseInput {
+                state: e_state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = e_state == ElementState::Pressed;
+                if self.dragging {
+                    // take over the camera from the auto-rotation
+                    state.auto_rotate = false;
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some((x, y)) = self.cursor_position
+                    && self.dragging
+                {
+                    let dx = (position.x - x) as f32;
+                    let dy = (position.y - y) as f32;
+                    state.camera_mut().rotate(dx * 0.005, dy * 0.005);
+                }
+                self.cursor_position = Some((position.x, position.y));
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let y = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+                };
+                state.camera_mut().zoom(0.9_f32.powf(y));
+            }
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match &event.logical_key {
+                    Key::Named(NamedKey::Space) => {
+                        state.auto_rotate = !state.auto_rotate;
+                    }
+                    Key::Character(x) if adjust_fractal_params(&mut state.fractal, x) => {
+                        println!("{:?}", state.fractal);
+                    }
+                    _ => {}
+                }
+            }
             WindowEvent::RedrawRequested => {
                 let Some(w) = &self.window else {
                     return;
@@ -89,11 +193,10 @@ impl ApplicationHandler for App {
                 state.update();
                 state.render(|| w.pre_present_notify());
 
-                // print the FPS
-                let ts = Local::now().timestamp_nanos_opt().unwrap() as u64;
-                let fps = 1_000_000_000.0 / (ts - self.last_frame_time) as f64;
-                println!("FPS: {:.2}", fps.floor());
-                self.last_frame_time = ts;
+                self.frame_stats.tick();
+                if self.frame_stats.report_due() {
+                    println!("{}", self.frame_stats);
+                }
 
                 w.request_redraw();
             }
@@ -102,7 +205,106 @@ impl ApplicationHandler for App {
     }
 }
 
-pub fn main() {
+/// Returns whether `key` is bound to a parameter.
+fn adjust_fractal_params(params: &mut FractalParams, key: &str) -> bool {
+    match key {
+        "1" => params.power = (params.power - 1.0).max(1.0),
+        "2" => params.power += 1.0,
+        "3" => params.bailout = (params.bailout - 0.5).max(0.5),
+        "4" => params.bailout += 0.5,
+        "5" => params.step_size /= 1.25,
+        "6" => params.step_size *= 1.25,
+        "7" => params.max_steps = params.max_steps.saturating_sub(100).max(100),
+        "8" => params.max_steps += 100,
+        "9" => params.refine_iterations = params.refine_iterations.saturating_sub(1),
+        "0" => params.refine_iterations += 1,
+        _ => return false,
+    }
+    true
+}
+
+const OFFSCREEN_SIZE: (u32, u32) = (1024, 1024);
+
+async fn record(dir: &Path, frames: u32, config: Config) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut state =
+        State::new_headless(wgpu_instance_with_env_backend(), OFFSCREEN_SIZE, config).await?;
+    for i in 0..frames {
+        state.update();
+        state.render(|| {});
+        let pixels = state.read_pixels().await?;
+        let path = dir.join(format!("{:05}.png", i));
+        image::save_buffer(
+            &path,
+            &pixels,
+            OFFSCREEN_SIZE.0,
+            OFFSCREEN_SIZE.1,
+            image::ColorType::Rgba8,
+        )?;
+        println!("Frame {}/{}: {}", i + 1, frames, path.display());
+    }
+    Ok(())
+}
+
+async fn bench(frames: u32, config: Config) -> anyhow::Result<()> {
+    /// Frames rendered before measuring, to let shader compilation and clocks settle.
+    const WARMUP_FRAMES: u32 = 5;
+
+    let config = Config {
+        gpu_timing: true,
+        ..config
+    };
+    let mut summaries = Vec::new();
+    for backend in Backends::from_env().unwrap_or(Backends::all()).iter() {
+        let instance = wgpu_instance_with_backends(backend);
+        if AdapterSelector::from_env()
+            .select(&instance, None)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+        // The camera starts from its default and auto-rotates by a fixed step per frame,
+        // so every run renders the same path.
+        let mut state = State::new_headless(instance, OFFSCREEN_SIZE, config).await?;
+        let info = state.adapter_info().clone();
+        info!("Benchmarking {:?} on {}", info.backend, info.name);
+
+        let mut frame_stats = FrameStats::new(frames as usize);
+        for i in 0..(WARMUP_FRAMES + frames) {
+            state.update();
+            let start = Instant::now();
+            state.render(|| {});
+            let frame_time = match state.read_gpu_time().await? {
+                Some(t) => t,
+                None => {
+                    state.wait_idle()?;
+                    start.elapsed()
+                }
+            };
+            if i >= WARMUP_FRAMES {
+                frame_stats.record(frame_time);
+            }
+        }
+
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let summary = frame_stats.summary();
+        summaries.push(BenchSummary {
+            backend: format!("{:?}", info.backend),
+            adapter: info.name,
+            timing: if state.has_gpu_timer() { "gpu" } else { "cpu" },
+            frames: summary.frames,
+            min_ms: ms(summary.min),
+            avg_ms: ms(summary.avg),
+            p99_ms: ms(summary.p99),
+        });
+    }
+
+    println!("{}", serde_json::to_string_pretty(&summaries)?);
+    Ok(())
+}
+
+pub fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     unsafe {
@@ -110,15 +312,41 @@ pub fn main() {
     }
     env_logger::init();
 
+    // same floor as the `1` key
+    if args.power.is_nan() || args.power < 1.0 {
+        anyhow::bail!("--power must be at least 1, got {}", args.power);
+    }
+
+    let config = Config {
+        kernel_iterations: args.kernel_iterations,
+        fractal: FractalParams {
+            power: args.power,
+            bailout: args.bailout,
+            step_size: args.step_size,
+            max_steps: args.max_steps,
+            refine_iterations: args.refine_iterations,
+        },
+        ..default!()
+    };
+    if let Some(dir) = &args.record {
+        return pollster::block_on(record(dir, args.frames, config));
+    }
+    if args.bench {
+        return pollster::block_on(bench(args.frames, config));
+    }
+
     let event_loop = EventLoop::new().unwrap();
 
     event_loop.set_control_flow(ControlFlow::Wait);
 
     let mut app = App {
-        animation_config: Config {
-            kernel_iterations: args.kernel_iterations,
-        },
+        animation_config: config,
+        present: (&args.present).into(),
         ..default!()
     };
     event_loop.run_app(&mut app).unwrap();
+    match app.error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
@@ -1,11 +1,24 @@
 #![feature(decl_macro)]
 
+pub mod surface;
 pub mod triangle_rotation;
 pub mod vsbm;
 
+use crate::surface::{PresentConfig, SurfaceManager};
+use anyhow::{anyhow, bail};
+use std::collections::VecDeque;
 use std::env;
+use std::fmt::{self, Display, Formatter};
 use std::time::{Duration, Instant};
-use wgpu::{Backends, Color, Instance, Surface};
+use tokio::sync::oneshot;
+use wgpu::wgt::PollType;
+use wgpu::{
+    Adapter, Backends, Buffer, BufferDescriptor, BufferUsages, Color, CommandEncoder, Device,
+    DeviceType, Extent3d, Instance, MapMode, PowerPreference, QuerySet, QuerySetDescriptor,
+    QueryType, Queue, RenderPassTimestampWrites, Surface, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages,
+};
 
 pub fn set_up_logger() {
     unsafe {
@@ -19,12 +32,121 @@ pub macro default() {
 }
 
 pub fn wgpu_instance_with_env_backend() -> Instance {
-    let backends = Backends::from_env().unwrap_or(Backends::all());
+    wgpu_instance_with_backends(Backends::from_env().unwrap_or(Backends::all()))
+}
+
+pub fn wgpu_instance_with_backends(backends: Backends) -> Instance {
     let mut desc = wgpu::InstanceDescriptor::new_without_display_handle();
     desc.backends = backends;
     Instance::new(desc)
 }
 
+/// Chooses an adapter by more than what `request_adapter` offers.
+///
+/// Without a name or device type this is just `request_adapter` with the power preference
+/// and fallback flag. Otherwise all adapters are enumerated and the first match, ordered by
+/// power preference, is taken.
+#[derive(Debug, Clone, Default)]
+pub struct AdapterSelector {
+    /// Case-insensitive substring of the adapter name, e.g. `llvmpipe` or `nvidia`
+    pub name: Option<String>,
+    pub device_type: Option<DeviceType>,
+    pub power_preference: PowerPreference,
+    /// Only accept a software (CPU) adapter
+    pub force_fallback_adapter: bool,
+}
+
+impl AdapterSelector {
+    /// Reads the selection from the environment, next to `WGPU_BACKEND`:
+    ///
+    /// - `WGPU_ADAPTER_NAME`: adapter name substring
+    /// - `WGPU_DEVICE_TYPE`: `discrete`, `integrated`, `virtual`, `cpu` or `other`
+    /// - `WGPU_POWER_PREF`: `low`, `high` or `none`
+    /// - `WGPU_FORCE_FALLBACK_ADAPTER`: `1` or `true`
+    pub fn from_env() -> Self {
+        Self {
+            name: env::var("WGPU_ADAPTER_NAME").ok(),
+            device_type: env::var("WGPU_DEVICE_TYPE")
+                .ok()
+                .and_then(|x| parse_device_type(&x)),
+            power_preference: PowerPreference::from_env().unwrap_or_default(),
+            force_fallback_adapter: env::var("WGPU_FORCE_FALLBACK_ADAPTER")
+                .is_ok_and(|x| x == "1" || x.eq_ignore_ascii_case("true")),
+        }
+    }
+
+    pub fn matches(&self, adapter: &Adapter) -> bool {
+        let info = adapter.get_info();
+        if let Some(name) = &self.name
+            && !info.name.to_lowercase().contains(&name.to_lowercase())
+        {
+            return false;
+        }
+        if let Some(device_type) = self.device_type
+            && info.device_type != device_type
+        {
+            return false;
+        }
+        !self.force_fallback_adapter || info.device_type == DeviceType::Cpu
+    }
+
+    pub async fn select(
+        &self,
+        instance: &Instance,
+        compatible_surface: Option<&Surface<'_>>,
+    ) -> anyhow::Result<Adapter> {
+        if self.name.is_none() && self.device_type.is_none() {
+            return Ok(instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: self.power_preference,
+                    force_fallback_adapter: self.force_fallback_adapter,
+                    compatible_surface,
+                })
+                .await?);
+        }
+
+        let adapters = instance.enumerate_adapters(Backends::all()).await;
+        let names = adapters
+            .iter()
+            .map(|x| x.get_info().name)
+            .collect::<Vec<_>>();
+        let mut candidates = adapters
+            .into_iter()
+            .filter(|x| self.matches(x))
+            .filter(|x| compatible_surface.is_none_or(|s| x.is_surface_supported(s)))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|x| self.power_rank(x.get_info().device_type));
+        match candidates.into_iter().next() {
+            Some(adapter) => Ok(adapter),
+            None => bail!("No adapter matches {:?}; available: {:?}", self, names),
+        }
+    }
+
+    /// Lower is preferred.
+    fn power_rank(&self, device_type: DeviceType) -> u8 {
+        match (self.power_preference, device_type) {
+            (PowerPreference::None, _) => 0,
+            (PowerPreference::HighPerformance, DeviceType::DiscreteGpu) => 0,
+            (PowerPreference::LowPower, DeviceType::IntegratedGpu) => 0,
+            (_, DeviceType::DiscreteGpu | DeviceType::IntegratedGpu) => 1,
+            (_, DeviceType::VirtualGpu) => 2,
+            (_, DeviceType::Cpu) => 3,
+            (_, DeviceType::Other) => 4,
+        }
+    }
+}
+
+fn parse_device_type(s: &str) -> Option<DeviceType> {
+    match s.to_lowercase().as_str() {
+        "discrete" => Some(DeviceType::DiscreteGpu),
+        "integrated" => Some(DeviceType::IntegratedGpu),
+        "virtual" => Some(DeviceType::VirtualGpu),
+        "cpu" => Some(DeviceType::Cpu),
+        "other" => Some(DeviceType::Other),
+        _ => None,
+    }
+}
+
 pub trait ColorExt {
     fn from_vec4d(x: [f64; 4]) -> Self;
 }
@@ -52,33 +174,377 @@ pub struct WgpuStateInitInfo {
     pub instance: Instance,
     pub surface: Surface<'static>,
     pub size: (u32, u32),
+    pub present: PresentConfig,
+}
+
+/// Where a State draws its frames to.
+pub enum RenderTarget {
+    Surface(SurfaceManager),
+    /// An owned offscreen texture. Used for headless rendering; its content can
+    /// be read back with [`read_texture`].
+    Texture(Texture),
+}
+
+impl RenderTarget {
+    pub fn offscreen(device: &Device, size: (u32, u32), format: TextureFormat) -> Self {
+        Self::Texture(create_offscreen_texture(device, size, format))
+    }
+
+    /// The format render pipelines drawing to this target should use.
+    pub fn format(&self) -> TextureFormat {
+        match self {
+            RenderTarget::Surface(surface) => surface.format(),
+            RenderTarget::Texture(texture) => texture.format(),
+        }
+    }
 }
 
-pub struct FpsCounter {
-    instant: Instant,
-    counter: usize,
+pub fn create_offscreen_texture(
+    device: &Device,
+    size: (u32, u32),
+    format: TextureFormat,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
 }
 
-impl Default for FpsCounter {
+/// Copies the first mip level of `texture` to the CPU.
+///
+/// Rows are returned tightly packed (without the `COPY_BYTES_PER_ROW_ALIGNMENT` padding
+/// required by the copy).
+pub async fn read_texture(
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+) -> anyhow::Result<Vec<u8>> {
+    let format = texture.format();
+    let bytes_per_pixel = format
+        .block_copy_size(None)
+        .ok_or_else(|| anyhow!("Unsupported texture format for reading: {:?}", format))?;
+    let (width, height) = (texture.width(), texture.height());
+    let unpadded_bytes_per_row = width * bytes_per_pixel;
+    let padded_bytes_per_row =
+        unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Texture Read Buffer"),
+        size: padded_bytes_per_row as u64 * height as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&default!());
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit([encoder.finish()]);
+    map_read(device, &buffer).await?;
+
+    let mapped = buffer.get_mapped_range(..);
+    let mut pixels = Vec::with_capacity(unpadded_bytes_per_row as usize * height as usize);
+    for row in mapped.chunks(padded_bytes_per_row as usize) {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+    drop(mapped);
+    buffer.unmap();
+    Ok(pixels)
+}
+
+async fn map_read(device: &Device, buffer: &Buffer) -> anyhow::Result<()> {
+    let (tx, rx) = oneshot::channel();
+    buffer.map_async(MapMode::Read, .., |e| {
+        tx.send(e).unwrap();
+    });
+    device.poll(PollType::Wait {
+        submission_index: None,
+        timeout: None,
+    })?;
+    rx.await??;
+    Ok(())
+}
+
+/// Measures the GPU time of a single render pass with timestamp queries.
+///
+/// Requires [`wgpu::Features::TIMESTAMP_QUERY`].
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    read_buffer: Buffer,
+}
+
+impl GpuTimer {
+    const QUERY_BYTES: u64 = 2 * size_of::<u64>() as u64;
+
+    pub fn new(device: &Device) -> Self {
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("GPU Timer Queries"),
+            ty: QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("GPU Timer Resolve Buffer"),
+            size: Self::QUERY_BYTES,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let read_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("GPU Timer Read Buffer"),
+            size: Self::QUERY_BYTES,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            query_set,
+            resolve_buffer,
+            read_buffer,
+        }
+    }
+
+    pub fn timestamp_writes(&self) -> RenderPassTimestampWrites<'_> {
+        RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    /// Call this after the timed pass has ended, in the same encoder.
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.read_buffer, 0, None);
+    }
+
+    /// Waits for the submitted work and returns the duration of the last timed pass.
+    pub async fn read(&self, device: &Device, queue: &Queue) -> anyhow::Result<Duration> {
+        map_read(device, &self.read_buffer).await?;
+        let mapped = self.read_buffer.get_mapped_range(..);
+        let timestamps: &[u64] = bytemuck::cast_slice(&mapped);
+        let ticks = timestamps[1].wrapping_sub(timestamps[0]);
+        drop(mapped);
+        self.read_buffer.unmap();
+        Ok(Duration::from_nanos(
+            (ticks as f64 * queue.get_timestamp_period() as f64) as u64,
+        ))
+    }
+}
+
+/// A clock that only advances while not paused.
+pub struct PausableClock {
+    start: Option<Instant>,
+    elapsed: Duration,
+}
+
+impl Default for PausableClock {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl FpsCounter {
+impl PausableClock {
     pub fn new() -> Self {
         Self {
-            instant: Instant::now(),
-            counter: 0,
+            start: Some(Instant::now()),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match self.start {
+            Some(t) => self.elapsed + t.elapsed(),
+            None => self.elapsed,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.start.is_none()
+    }
+
+    pub fn switch_pause(&mut self) {
+        match self.start {
+            Some(t) => {
+                self.elapsed += t.elapsed();
+                self.start = None;
+            }
+            None => {
+                self.start = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Frame timing statistics over a rolling window of recent frames.
+///
+/// Call [`FrameStats::tick`] once per frame, or feed externally measured durations
+/// (e.g. GPU timestamps) with [`FrameStats::record`]. While paused, ticks are ignored and
+/// the clock stands still, so a paused animation doesn't skew the statistics.
+pub struct FrameStats {
+    clock: PausableClock,
+    last_tick: Option<Instant>,
+    last_report: Instant,
+    frame_times: VecDeque<Duration>,
+    window: usize,
+    total_frames: u64,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameSummary {
+    pub frames: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW)
+    }
+}
+
+impl FrameStats {
+    pub const DEFAULT_WINDOW: usize = 120;
+    const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// `window`: number of most recent frames the statistics are computed over
+    pub fn new(window: usize) -> Self {
+        Self {
+            clock: PausableClock::new(),
+            last_tick: None,
+            last_report: Instant::now(),
+            frame_times: VecDeque::with_capacity(window),
+            window: window.max(1),
+            total_frames: 0,
+        }
+    }
+
+    /// Marks the end of a frame and records the time since the previous tick.
+    pub fn tick(&mut self) {
+        if self.clock.is_paused() {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_tick.replace(now) {
+            self.record(now - last);
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        self.total_frames += 1;
+    }
+
+    /// Animation time, excluding paused periods.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    pub fn switch_pause(&mut self) {
+        self.clock.switch_pause();
+        // the gap spent in pause is not a frame
+        self.last_tick = None;
+    }
+
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.frame_times.back().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        Some(self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32)
+    }
+
+    pub fn fps(&self) -> f64 {
+        match self.avg() {
+            Some(avg) if !avg.is_zero() => 1.0 / avg.as_secs_f64(),
+            _ => 0.0,
         }
     }
 
-    pub fn hint_and_get(&mut self) -> (Duration, f32) {
-        self.counter += 1;
-        let duration = self.instant.elapsed();
-        (
-            duration,
-            (self.counter as f64 / duration.as_secs_f64()) as f32,
+    pub fn min(&self) -> Option<Duration> {
+        self.frame_times.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.frame_times.iter().max().copied()
+    }
+
+    /// Nearest-rank percentile; `p` is in `0.0..=100.0`.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+        let mut sorted = self.frame_times.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+        let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    pub fn summary(&self) -> FrameSummary {
+        FrameSummary {
+            frames: self.frame_times.len(),
+            min: self.min().unwrap_or_default(),
+            avg: self.avg().unwrap_or_default(),
+            p99: self.percentile(99.0).unwrap_or_default(),
+            max: self.max().unwrap_or_default(),
+        }
+    }
+
+    /// Returns `true` at most once per second. Used to throttle printing the stats.
+    pub fn report_due(&mut self) -> bool {
+        if self.last_report.elapsed() < Self::REPORT_INTERVAL {
+            return false;
+        }
+        self.last_report = Instant::now();
+        true
+    }
+}
+
+impl Display for FrameStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let summary = self.summary();
+        write!(
+            f,
+            "FPS: {:.2}, frame time avg {:.2} ms, min {:.2} ms, max {:.2} ms, p99 {:.2} ms",
+            self.fps(),
+            ms(summary.avg),
+            ms(summary.min),
+            ms(summary.max),
+            ms(summary.p99)
         )
     }
 }
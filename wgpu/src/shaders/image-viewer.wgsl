@@ -2,19 +2,167 @@ struct Info {
     image_size: vec2u,
     out_size: vec2u,
     uv_offset: vec2f,
-    _pad1: vec2u,
+    // Zoom relative to fitting the image in the window
+    scale: f32,
+    tile_cols: u32,
+    // Size of one tile; see `TileGrid`
+    tile_size: vec2u,
     no_scale: u32,
     proportional: u32,
-    _pad2: u32,
-    _pad3: u32,
+    // In EV; only applied to linear images
+    exposure: f32,
+    tone_map: u32,
+    linear_input: u32,
+    // Bicubic instead of bilinear filtering when magnifying
+    bicubic: u32,
+    // Bits 0-1: clockwise quarter turns; bit 2: flip horizontally after rotating
+    orientation: u32,
+    brightness: f32,
+    contrast: f32,
+    gamma: f32,
+    saturation: f32,
+    invert: u32,
+    // 0: RGB, 1-4: R/G/B/A only, 5: luminance; see `ChannelView`
+    channel: u32,
+    // Output the alpha instead of compositing over the checkerboard
+    keep_alpha: u32,
 }
 
 @group(0) @binding(0) var<uniform> info: Info;
 @group(0) @binding(1) var samp: sampler;
-@group(0) @binding(2) var texture: texture_2d<f32>;
+// One layer per tile, row by row
+@group(0) @binding(2) var texture: texture_2d_array<f32>;
 
+// Size of the image as shown, i.e. after applying the orientation
+fn display_size() -> vec2u {
+    return select(info.image_size, info.image_size.yx, (info.orientation & 1) == 1);
+}
+
+// Maps a texture coordinate in the shown image to one in the stored image
+fn orient_uv(uv: vec2f) -> vec2f {
+    // rotate around the center
+    var p = uv - 0.5;
+    if (info.orientation & 4) != 0 {
+        p.x = -p.x;
+    }
+    switch info.orientation & 3 {
+        case 1: { p = vec2f(p.y, -p.x); }
+        case 2: { p = -p; }
+        case 3: { p = vec2f(-p.y, p.x); }
+        default: {}
+    }
+    return p + 0.5;
+}
+
+fn tile_layer(tile: vec2u) -> u32 {
+    return tile.y * info.tile_cols + tile.x;
+}
+
+fn load_pixel(pixel: vec2u) -> vec4f {
+    let tile = pixel / info.tile_size;
+    return textureLoad(texture, pixel - tile * info.tile_size, tile_layer(tile), 0);
+}
+
+// `uv_dx` and `uv_dy` are the screen-space derivatives of `uv`, for picking the mip level.
+fn sample_image(uv: vec2f, uv_dx: vec2f, uv_dy: vec2f) -> vec4f {
+    let image_size = vec2f(info.image_size);
+    let tile_size = vec2f(info.tile_size);
+    let pixel = uv * image_size;
+    let last_tile = (info.image_size - 1) / info.tile_size;
+    let tile = min(vec2u(pixel / tile_size), last_tile);
+    let tile_origin = vec2f(tile * info.tile_size);
+    // Stay within the filled part of the tile so that filtering doesn't pick up the unused
+    // texels of partial tiles.
+    let filled = min(tile_size, image_size - tile_origin);
+    let local = clamp(pixel - tile_origin, vec2f(0.5), filled - 0.5);
+    // `uv` spans the whole image while the texture coordinates span one tile
+    let grad_scale = image_size / tile_size;
+    return textureSampleGrad(
+        texture, samp, local / tile_size, tile_layer(tile), uv_dx * grad_scale, uv_dy * grad_scale
+    );
+}
+
+// Catmull-Rom weights of the four taps around a sample at fraction `t`
+fn catmull_rom(t: f32) -> vec4f {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    return vec4f(
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.0,
+        -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2,
+    );
+}
+
+fn sample_image_bicubic(uv: vec2f) -> vec4f {
+    let pixel = uv * vec2f(info.image_size) - 0.5;
+    let base = floor(pixel);
+    let f = pixel - base;
+    let wx = catmull_rom(f.x);
+    let wy = catmull_rom(f.y);
+    let max_pixel = vec2i(info.image_size) - 1;
+    var color = vec4f(0);
+    for (var y = 0; y < 4; y++) {
+        for (var x = 0; x < 4; x++) {
+            let p = clamp(vec2i(base) + vec2i(x - 1, y - 1), vec2i(0), max_pixel);
+            color += load_pixel(vec2u(p)) * wx[x] * wy[y];
+        }
+    }
+    // Catmull-Rom overshoots at sharp edges. Linear input may legitimately exceed 1 before
+    // `develop`, so only its alpha is capped.
+    color = max(color, vec4f(0));
+    if info.linear_input == 1 {
+        color.a = min(color.a, 1.0);
+    } else {
+        color = min(color, vec4f(1));
+    }
+    return color;
+}
+
+fn checkerboard(pos: vec2f) -> vec3f {
+    let cell = vec2u(pos / 8.0);
+    return select(vec3f(0.4), vec3f(0.6), (cell.x + cell.y) % 2 == 0);
+}
+
+fn linear_to_srgb(x: vec3f) -> vec3f {
+    return select(1.055 * pow(x, vec3f(1.0 / 2.4)) - 0.055, x * 12.92, x <= vec3f(0.0031308));
+}
+
+// Turns linear light into the sRGB-encoded values the surface expects
+fn develop(color: vec3f) -> vec3f {
+    var c = color * exp2(info.exposure);
+    if info.tone_map == 1 {
+        // Reinhard
+        c = c / (1.0 + c);
+    }
+    return linear_to_srgb(clamp(c, vec3f(0), vec3f(1)));
+}
+
+// Rec. 709 luma coefficients
+const LUMA = vec3f(0.2126, 0.7152, 0.0722);
+
+// Applies the user's adjustments to displayed (sRGB-encoded) values
+fn adjust(color: vec3f) -> vec3f {
+    var c = (color - 0.5) * info.contrast + 0.5 + info.brightness;
+    c = mix(vec3f(dot(c, LUMA)), c, info.saturation);
+    c = pow(max(c, vec3f(0)), vec3f(1.0 / info.gamma));
+    if info.invert == 1 {
+        c = 1.0 - c;
+    }
+    return clamp(c, vec3f(0), vec3f(1));
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4f,
+    // Position in the shown image; outside of 0..1 is off the image
+    @location(0) display_uv: vec2f,
+    // Position in the stored image, i.e. `display_uv` with the orientation applied
+    @location(1) image_uv: vec2f,
+}
+
+// The view and the orientation are affine, so mapping the corners of the window is enough.
 @vertex
-fn vs(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4f {
+fn vs(@builtin(vertex_index) vi: u32) -> VertexOutput {
     let quad_vertices = array(
         vec2f(-1, -1),
         vec2f(-1, 1),
@@ -23,40 +171,82 @@ fn vs(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4f {
         vec2f(1, -1),
         vec2f(-1, -1),
     );
-    return vec4f(quad_vertices[vi], 0, 1);
+    let corner = quad_vertices[vi];
+    // Window coordinates from the top left, like `@builtin(position)` in `fs`
+    var uv = vec2f(corner.x, -corner.y) * 0.5 + 0.5;
+
+    var display_uv: vec2f;
+    if info.no_scale == 1 {
+        display_uv = uv * vec2f(info.out_size) / vec2f(display_size());
+    } else {
+        if info.proportional == 1 {
+            let input_aspect = f32(display_size().x) / f32(display_size().y);
+            let output_aspect = f32(info.out_size.x) / f32(info.out_size.y);
+            if input_aspect > output_aspect {
+                let s = output_aspect / input_aspect;
+                uv.y = (uv.y - 0.5) / s + 0.5;
+            } else {
+                let s = input_aspect / output_aspect;
+                uv.x = (uv.x - 0.5) / s + 0.5;
+            }
+        }
+        display_uv = (uv - 0.5) / info.scale + 0.5 + info.uv_offset;
+    }
+
+    var out: VertexOutput;
+    out.position = vec4f(corner, 0, 1);
+    out.display_uv = display_uv;
+    out.image_uv = orient_uv(display_uv);
+    return out;
 }
 
 @fragment
-fn fs(@builtin(position) fs_pos: vec4f) -> @location(0) vec4f {
+fn fs(in: VertexOutput) -> @location(0) vec4f {
     const BLACK = vec4f(0, 0, 0, 1);
-
-    let s = samp;
-
-    let u = fs_pos.x / f32(info.out_size.x);
-    let v = fs_pos.y / f32(info.out_size.y);
-
-    var uv = vec2f(u, v);
-    if info.proportional == 1 {
-        let input_aspect = f32(info.image_size.x) / f32(info.image_size.y);
-        let output_aspect = f32(info.out_size.x) / f32(info.out_size.y);
-        if input_aspect > output_aspect {
-            let s = output_aspect / input_aspect;
-            uv.y = (uv.y - 0.5) / s + 0.5;
-        } else {
-            let s = input_aspect / output_aspect;
-            uv.x = (uv.x - 0.5) / s + 0.5;
-        }
-    }
+    let fs_pos = in.position;
+    let display_uv = in.display_uv;
+    let image_uv = in.image_uv;
 
     var color: vec4f;
     if info.no_scale == 1 {
-        color = textureLoad(texture, vec2u(fs_pos.xy), 0);
+        if any(display_uv >= vec2f(1)) {
+            return BLACK;
+        }
+        let pixel = vec2u(image_uv * vec2f(info.image_size));
+        color = load_pixel(min(pixel, info.image_size - 1));
     } else {
-        let sampled_uv = uv + info.uv_offset;
-        if sampled_uv.x < 0 || sampled_uv.x > 1 || sampled_uv.y < 0 || sampled_uv.y > 1 {
+        // Derivatives must be taken before the non-uniform return below
+        let uv_dx = dpdx(image_uv);
+        let uv_dy = dpdy(image_uv);
+        if any(display_uv < vec2f(0)) || any(display_uv > vec2f(1)) {
             return BLACK;
         }
-        color = textureSample(texture, s, sampled_uv);
+        // Image pixels covered by one screen pixel
+        let image_size = vec2f(info.image_size);
+        let footprint = max(length(uv_dx * image_size), length(uv_dy * image_size));
+        if info.bicubic == 1 && footprint < 1.0 {
+            color = sample_image_bicubic(image_uv);
+        } else {
+            color = sample_image(image_uv, uv_dx, uv_dy);
+        }
     }
-    return color;
+
+    var rgb = color.rgb;
+    if info.linear_input == 1 {
+        rgb = develop(rgb);
+    }
+    // Single channel views are opaque grayscale
+    switch info.channel {
+        case 1: { return vec4f(adjust(vec3f(rgb.r)), 1); }
+        case 2: { return vec4f(adjust(vec3f(rgb.g)), 1); }
+        case 3: { return vec4f(adjust(vec3f(rgb.b)), 1); }
+        case 4: { return vec4f(adjust(vec3f(color.a)), 1); }
+        case 5: { return vec4f(adjust(vec3f(dot(rgb, LUMA))), 1); }
+        default: {}
+    }
+    if info.keep_alpha == 1 {
+        return vec4f(adjust(rgb), color.a);
+    }
+    // Show transparent parts over a checkerboard
+    return vec4f(mix(checkerboard(fs_pos.xy), adjust(rgb), color.a), 1);
 }
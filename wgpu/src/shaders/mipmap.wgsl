@@ -0,0 +1,24 @@
+// Downsamples one mip level into the next; the linear sampler averages 2x2 texels.
+
+@group(0) @binding(0) var samp: sampler;
+@group(0) @binding(1) var src: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn vs(@builtin(vertex_index) vi: u32) -> VertexOutput {
+    // one triangle covering the whole target
+    let uv = vec2f(f32((vi << 1) & 2), f32(vi & 2));
+    var out: VertexOutput;
+    out.position = vec4f(uv * vec2f(2, -2) + vec2f(-1, 1), 0, 1);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs(in: VertexOutput) -> @location(0) vec4f {
+    return textureSample(src, samp, in.uv);
+}
@@ -1,23 +1,39 @@
-fn check_difficulty(buf: ptr<function, array<u32, SHA256_BLOCK_SIZE>>) -> bool { /* stub */; return true; }
-
 override WORKGROUP_SIZE = 0u;
 override ITERATIONS_PER_THREAD = 0u;
-override RUNS_PER_DISPATCH = 0u;
-override DIFFICULTY_BITS = 0u;
 
-struct SHA256_CTX {
-    data : array<u32, 64>,
-    datalen : u32,
-    bitlen : array<u32, 2>,
-    state : array<u32, 8>,
-    info : u32,
-  };
+// What to mine; built by `Job::shader_input`
+struct Job {
+  // SHA-256 state after the blocks before `tail`
+  midstate: array<u32, 8>,
+  // The hash must not exceed this; most significant word first
+  hash_target: array<u32, 8>,
+  // Number of 64-byte blocks in `tail`
+  tail_blocks: u32,
+  // Byte offset of the little-endian nonce in `tail`, and its width in bytes (at most 8)
+  nonce_offset: u32,
+  nonce_width: u32,
+  // First nonce of this dispatch
+  nonce_lo: u32,
+  nonce_hi: u32,
+  // Hash the digest again (Bitcoin)
+  double_hash: u32,
+  // Compare the hash as a little-endian number (Bitcoin)
+  hash_le: u32,
+  // Nonces from `nonce_lo`/`nonce_hi` on that haven't been swept yet
+  nonces_left: u32,
+  // The rest of the message, padded, as big-endian words; the nonce bytes are zero
+  tail: array<u32>,
+}
 
-  @group(0) @binding(0) var<storage, read> start : array<u32>;
-  @group(0) @binding(1) var<storage, read_write> result : array<u32>;
+// Appended to by all invocations of a dispatch
+struct Solutions {
+  count: atomic<u32>,
+  // May be shorter than `count`; further solutions are dropped
+  nonces: array<vec2<u32>>,
+}
 
-  const SHA256_BLOCK_SIZE = 32;
-  const INPUT_SIZE = 32;
+@group(0) @binding(0) var<storage, read> job: Job;
+@group(0) @binding(1) var<storage, read_write> solutions: Solutions;
 
   const k = array<u32, 64> (
     0x428a2f98,0x71374491,0xb5c0fbcf,0xe9b5dba5,0x3956c25b,0x59f111f1,0x923f82a4,0xab1c5ed5,
@@ -40,186 +56,114 @@ struct SHA256_CTX {
   fn SIG0(x : u32) -> u32{return (ROTRIGHT(x,7) ^ ROTRIGHT(x,18) ^ ((x) >> 3));}
   fn SIG1(x : u32) -> u32{return (ROTRIGHT(x,17) ^ ROTRIGHT(x,19) ^ ((x) >> 10));}
 
-  fn sha256_transform(ctx : ptr<function, SHA256_CTX>)
-  {
-    var a : u32;
-    var b : u32;
-    var c : u32;
-    var d : u32;
-    var e : u32;
-    var f : u32;
-    var g : u32;
-    var h : u32;
-    var i : u32 = 0;
-    var j : u32 = 0;
-    var t1 : u32;
-    var t2 : u32;
-    var m : array<u32, 64> ;
-
-
-    while(i < 16) {
-      m[i] = ((*ctx).data[j] << 24) | ((*ctx).data[j + 1] << 16) | ((*ctx).data[j + 2] << 8) | ((*ctx).data[j + 3]);
-      i++;
-      j += 4;
-    }
-
-    while(i < 64) {
-          m[i] = SIG1(m[i - 2]) + m[i - 7] + SIG0(m[i - 15]) + m[i - 16];
-      i++;
-    }
-
-    a = (*ctx).state[0];
-    b = (*ctx).state[1];
-    c = (*ctx).state[2];
-    d = (*ctx).state[3];
-    e = (*ctx).state[4];
-    f = (*ctx).state[5];
-    g = (*ctx).state[6];
-    h = (*ctx).state[7];
-
-    i = 0;
-    for (; i < 64; i++) {
-      t1 = h + EP1(e) + CH(e,f,g) + k[i] + m[i];
-      t2 = EP0(a) + MAJ(a,b,c);
-      h = g;
-      g = f;
-      f = e;
-      e = d + t1;
-      d = c;
-      c = b;
-      b = a;
-      a = t1 + t2;
-    }
-
+const SHA256_IV = array<u32, 8>(
+  0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+);
 
-    (*ctx).state[0] += a;
-    (*ctx).state[1] += b;
-    (*ctx).state[2] += c;
-    (*ctx).state[3] += d;
-    (*ctx).state[4] += e;
-    (*ctx).state[5] += f;
-    (*ctx).state[6] += g;
-    (*ctx).state[7] += h;
+// Compresses one 64-byte block, given as big-endian words, into `state`
+fn sha256_compress(state: ptr<function, array<u32, 8>>, block: array<u32, 16>) {
+  var m: array<u32, 64>;
+  for (var i = 0u; i < 16u; i++) {
+    m[i] = block[i];
+  }
+  for (var i = 16u; i < 64u; i++) {
+    m[i] = SIG1(m[i - 2]) + m[i - 7] + SIG0(m[i - 15]) + m[i - 16];
   }
 
+  var a = (*state)[0];
+  var b = (*state)[1];
+  var c = (*state)[2];
+  var d = (*state)[3];
+  var e = (*state)[4];
+  var f = (*state)[5];
+  var g = (*state)[6];
+  var h = (*state)[7];
+  for (var i = 0u; i < 64u; i++) {
+    let t1 = h + EP1(e) + CH(e, f, g) + k[i] + m[i];
+    let t2 = EP0(a) + MAJ(a, b, c);
+    h = g;
+    g = f;
+    f = e;
+    e = d + t1;
+    d = c;
+    c = b;
+    b = a;
+    a = t1 + t2;
+  }
+  (*state)[0] += a;
+  (*state)[1] += b;
+  (*state)[2] += c;
+  (*state)[3] += d;
+  (*state)[4] += e;
+  (*state)[5] += f;
+  (*state)[6] += g;
+  (*state)[7] += h;
+}
 
-  fn sha256_update(ctx : ptr<function, SHA256_CTX>, input_data: ptr<function, array<u32, INPUT_SIZE>>, len : u32)
-  {
-    for (var i :u32 = 0; i < len; i++) {
-      (*ctx).data[(*ctx).datalen] = input_data[i];
-      (*ctx).datalen++;
-      if ((*ctx).datalen == 64) {
-        sha256_transform(ctx);
-
-        if ((*ctx).bitlen[0] > 0xffffffff - (512)){
-          (*ctx).bitlen[1]++;
-        }
-        (*ctx).bitlen[0] += 512;
-
+fn swap_bytes(x: u32) -> u32 {
+  return (x << 24) | ((x << 8) & 0x00ff0000) | ((x >> 8) & 0x0000ff00) | (x >> 24);
+}
 
-        (*ctx).datalen = 0;
-      }
+fn meets_target(hash: array<u32, 8>) -> bool {
+  for (var i = 0u; i < 8u; i++) {
+    let h = select(hash[i], swap_bytes(hash[7 - i]), job.hash_le == 1);
+    let t = job.hash_target[i];
+    if h != t {
+      return h < t;
     }
   }
+  return true;
+}
 
-  fn sha256_final(ctx : ptr<function, SHA256_CTX>, hash:  ptr<function, array<u32, SHA256_BLOCK_SIZE>>  )
-  {
-    var i : u32 = (*ctx).datalen;
-
-    if ((*ctx).datalen < 56) {
-      (*ctx).data[i] = 0x80;
-        i++;
-      while (i < 56){
-        (*ctx).data[i] = 0x00;
-        i++;
-      }
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+  @builtin(num_workgroups) num_workgroups : vec3<u32>,
+) {
+  let runs_per_dispatch = num_workgroups.x * WORKGROUP_SIZE;
+  for (var i = 0u; i < ITERATIONS_PER_THREAD; i += 1) {
+    let addition = i * runs_per_dispatch + global_id.x;
+    if addition >= job.nonces_left {
+      break;
     }
-    else {
-      (*ctx).data[i] = 0x80;
-      i++;
-      while (i < 64){
-        (*ctx).data[i] = 0x00;
-        i++;
+    let nonce_lo = job.nonce_lo + addition;
+    let nonce_hi = job.nonce_hi + select(0u, 1u, nonce_lo < addition);
+
+    var state = job.midstate;
+    for (var b = 0u; b < job.tail_blocks; b++) {
+      var block: array<u32, 16>;
+      for (var j = 0u; j < 16u; j++) {
+        block[j] = job.tail[b * 16 + j];
       }
-      sha256_transform(ctx);
-      for (var i = 0; i < 56 ; i++) {
-        (*ctx).data[i] = 0;
+      // the nonce bytes falling into this block
+      for (var j = 0u; j < job.nonce_width; j++) {
+        let pos = job.nonce_offset + j;
+        if pos / 64 == b {
+          let byte = (select(nonce_lo, nonce_hi, j >= 4) >> (j % 4 * 8)) & 0xff;
+          let p = pos % 64;
+          block[p / 4] |= byte << ((3 - p % 4) * 8);
+        }
       }
+      sha256_compress(&state, block);
     }
 
-    if ((*ctx).bitlen[0] > 0xffffffff - (*ctx).datalen * 8) {
-      (*ctx).bitlen[1]++;
-    }
-    (*ctx).bitlen[0] += (*ctx).datalen * 8;
-
-
-    (*ctx).data[63] = (*ctx).bitlen[0];
-    (*ctx).data[62] = (*ctx).bitlen[0] >> 8;
-    (*ctx).data[61] = (*ctx).bitlen[0] >> 16;
-    (*ctx).data[60] = (*ctx).bitlen[0] >> 24;
-    (*ctx).data[59] = (*ctx).bitlen[1];
-    (*ctx).data[58] = (*ctx).bitlen[1] >> 8;
-    (*ctx).data[57] = (*ctx).bitlen[1] >> 16;
-    (*ctx).data[56] = (*ctx).bitlen[1] >> 24;
-    sha256_transform(ctx);
-
-    for (i = 0; i < 4; i++) {
-      (*hash)[i] = ((*ctx).state[0] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 4] = ((*ctx).state[1] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 8] = ((*ctx).state[2] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 12] = ((*ctx).state[3] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 16] = ((*ctx).state[4] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 20] = ((*ctx).state[5] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 24] = ((*ctx).state[6] >> (24 - i * 8)) & 0x000000ff;
-      (*hash)[i + 28] = ((*ctx).state[7] >> (24 - i * 8)) & 0x000000ff;
-    }
-  }
-
-  fn sha256_init(ctx : ptr<function, SHA256_CTX>) {
-    // CTX INIT
-    (*ctx).datalen = 0;
-    (*ctx).bitlen[0] = 0;
-    (*ctx).bitlen[1] = 0;
-    (*ctx).state[0] = 0x6a09e667;
-    (*ctx).state[1] = 0xbb67ae85;
-    (*ctx).state[2] = 0x3c6ef372;
-    (*ctx).state[3] = 0xa54ff53a;
-    (*ctx).state[4] = 0x510e527f;
-    (*ctx).state[5] = 0x9b05688c;
-    (*ctx).state[6] = 0x1f83d9ab;
-    (*ctx).state[7] = 0x5be0cd19;
-  }
-
-fn set_local_input_with_offset(p_data: ptr<function, array<u32, SHA256_BLOCK_SIZE>>, n: u32) {
-    var carry = n;
-
-    for (var i = 0u; i < 32u; i++) {
-        let sum = start[i] + carry;
-        (*p_data)[i] = sum & 255u;
-        carry = sum >> 8u;
+    if job.double_hash == 1 {
+      // SHA-256 of the 32-byte digest
+      var digest_block: array<u32, 16>;
+      for (var j = 0u; j < 8u; j++) {
+        digest_block[j] = state[j];
+      }
+      digest_block[8] = 0x80000000;
+      digest_block[15] = 32 * 8;
+      state = SHA256_IV;
+      sha256_compress(&state, digest_block);
     }
-}
-
-@compute @workgroup_size(WORKGROUP_SIZE)
-fn main(@builtin(global_invocation_id) global_id : vec3<u32>) {
-    let _r = result[0];
-
-    for (var i = 0u; i < ITERATIONS_PER_THREAD; i += 1) {
-      let addition = i * RUNS_PER_DISPATCH + global_id.x;
-      var this_input: array<u32, SHA256_BLOCK_SIZE>;
-
-      set_local_input_with_offset(&this_input, addition);
-      var ctx : SHA256_CTX;
-      sha256_init(&ctx);
-      var buf : array<u32, SHA256_BLOCK_SIZE>;
-      sha256_update(&ctx, &this_input, INPUT_SIZE);
-      sha256_final(&ctx, &buf);
 
-      if check_difficulty(&buf) {
-        for (var j = 0u; j < 32; j += 1) {
-          // result[j] = buf[j];
-          result[j] = this_input[j];
-        }
+    if meets_target(state) {
+      let index = atomicAdd(&solutions.count, 1u);
+      if index < arrayLength(&solutions.nonces) {
+        solutions.nonces[index] = vec2(nonce_lo, nonce_hi);
       }
     }
+  }
 }
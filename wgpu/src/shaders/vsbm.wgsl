@@ -11,7 +11,11 @@ struct Uniforms {
     _p4: f32,
     screen_size: vec2f,
     len: f32,
-    _p5: f32,
+    power: f32,
+    bailout: f32,
+    step_size: f32,
+    max_steps: u32,
+    refine_iterations: u32,
 };
 
 @group(0) @binding(0) var<uniform> ui: Uniforms;
@@ -38,11 +42,11 @@ fn kernel(ver: vec3f) -> f32 {
     var b: f32; var c: f32; var d: f32;
     for(var i: i32 = 0; i < KERNEL_ITERATIONS; i++) {
         b = length(a);
-        c = atan2(a.y, a.x) * 8.0;
-        d = acos(a.z / b) * 8.0;
-        b = pow(b, 8.0);
+        c = atan2(a.y, a.x) * ui.power;
+        d = acos(a.z / b) * ui.power;
+        b = pow(b, ui.power);
         a = vec3f(b * sin(d) * cos(c), b * sin(d) * sin(c), b * cos(d)) + ver;
-        if (b > 6.0) { break; }
+        if (b > ui.bailout) { break; }
     }
     return 4.0 - dot(a, a);
 }
@@ -51,7 +55,8 @@ fn kernel(ver: vec3f) -> f32 {
 fn fs_main(@location(0) uv: vec2f) -> @location(0) vec4f {
     let M_L = 0.381966;
     let M_R = 0.618033;
-    let step_size = 0.002;
+    let step_size = ui.step_size;
+    let refine_iterations = i32(ui.refine_iterations);
 
     let dir = ui.forward + ui.right * uv.x * ui.screen_size.x + ui.up * uv.y * ui.screen_size.y;
     let local_dir = normalize(vec3f(uv.x * ui.screen_size.x, uv.y * ui.screen_size.y, -1.0));
@@ -61,14 +66,14 @@ fn fs_main(@location(0) uv: vec2f) -> @location(0) vec4f {
     var sign = 0;
     var r3: f32 = 0.0;
 
-    for (var k: i32 = 2; k < 1002; k++) {
+    for (var k: i32 = 2; k < i32(ui.max_steps) + 2; k++) {
         let ver = ui.origin + dir * (step_size * ui.len * f32(k));
         let v = kernel(ver);
 
         if (v > 0.0 && v1 < 0.0) {
             var r1 = step_size * ui.len * f32(k - 1);
             var r2 = step_size * ui.len * f32(k);
-            for (var l = 0; l < 8; l++) {
+            for (var l = 0; l < refine_iterations; l++) {
                 r3 = r1 * 0.5 + r2 * 0.5;
                 if (kernel(ui.origin + dir * r3) > 0.0) { r2 = r3; } else { r1 = r3; }
             }
@@ -83,7 +88,7 @@ fn fs_main(@location(0) uv: vec2f) -> @location(0) vec4f {
             var m2 = kernel(ui.origin + dir * r2);
             var m3 = kernel(ui.origin + dir * r3_g);
 
-            for (var l = 0; l < 8; l++) {
+            for (var l = 0; l < refine_iterations; l++) {
                 if (m2 > m3) {
                     r4 = r3_g; r3_g = r2; r2 = r4 * M_L + r1 * M_R;
                     m3 = m2; m2 = kernel(ui.origin + dir * r2);
@@ -97,7 +102,7 @@ fn fs_main(@location(0) uv: vec2f) -> @location(0) vec4f {
             if (kernel(ui.origin + dir * target_r) > 0.0) {
                 var ra = step_size * ui.len * f32(k - 2);
                 var rb = target_r;
-                for (var l = 0; l < 8; l++) {
+                for (var l = 0; l < refine_iterations; l++) {
                     r3 = ra * 0.5 + rb * 0.5;
                     if (kernel(ui.origin + dir * r3) > 0.0) { rb = r3; } else { ra = r3; }
                 }
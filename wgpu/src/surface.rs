@@ -0,0 +1,246 @@
+use anyhow::bail;
+use clap::ValueEnum;
+use log::{error, info};
+use wgpu::{
+    Adapter, CompositeAlphaMode, CurrentSurfaceTexture, Device, PresentMode, Surface,
+    SurfaceConfiguration, SurfaceTexture, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor,
+};
+
+/// Whether colors written to the surface get gamma-encoded by wgpu.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SrgbPolicy {
+    /// Render through an sRGB view. Shaders output linear colors and wgpu does the
+    /// gamma encoding.
+    Srgb,
+    /// Do not use srgb suffix. This makes wgpu think all colors we give are already in a
+    /// non-linear sRGB space and do not do an automatic gamma correction.
+    NoSrgb,
+}
+
+impl SrgbPolicy {
+    fn apply(self, format: TextureFormat) -> TextureFormat {
+        match self {
+            SrgbPolicy::Srgb => format.add_srgb_suffix(),
+            SrgbPolicy::NoSrgb => format.remove_srgb_suffix(),
+        }
+    }
+}
+
+/// How frames are handed to the presentation engine.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PresentConfig {
+    pub present_mode: PresentMode,
+    /// Maximum number of frames queued for presentation. This is a hint; wgpu clamps it
+    /// to what the backend supports.
+    pub desired_maximum_frame_latency: u32,
+}
+
+impl Default for PresentConfig {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::AutoVsync,
+            desired_maximum_frame_latency: 2,
+        }
+    }
+}
+
+/// [`PresentMode`] as a command line value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum PresentModeArg {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentModeArg> for PresentMode {
+    fn from(value: PresentModeArg) -> Self {
+        match value {
+            PresentModeArg::AutoVsync => PresentMode::AutoVsync,
+            PresentModeArg::AutoNoVsync => PresentMode::AutoNoVsync,
+            PresentModeArg::Fifo => PresentMode::Fifo,
+            PresentModeArg::FifoRelaxed => PresentMode::FifoRelaxed,
+            PresentModeArg::Mailbox => PresentMode::Mailbox,
+            PresentModeArg::Immediate => PresentMode::Immediate,
+        }
+    }
+}
+
+/// Command line options for a [`PresentConfig`]. Flatten it into a binary's `Args`.
+#[derive(Debug, Clone, clap::Args)]
+// Keep the doc comment above out of the binaries' `--help`.
+#[command(about = None, long_about = None)]
+pub struct PresentArgs {
+    /// Surface present mode
+    #[arg(long, value_enum, default_value_t = PresentModeArg::AutoVsync)]
+    pub present_mode: PresentModeArg,
+    /// Maximum number of frames queued for presentation
+    #[arg(long, default_value = "2")]
+    pub frame_latency: u32,
+}
+
+impl From<&PresentArgs> for PresentConfig {
+    fn from(value: &PresentArgs) -> Self {
+        Self {
+            present_mode: value.present_mode.into(),
+            desired_maximum_frame_latency: value.frame_latency,
+        }
+    }
+}
+
+/// Owns a window surface and its configuration.
+///
+/// Handles format selection, (re)configuration and acquiring frames, so render states
+/// don't each need their own copy of the `CurrentSurfaceTexture` handling.
+pub struct SurfaceManager {
+    surface: Surface<'static>,
+    device: Device,
+    /// Format the surface is configured with
+    surface_format: TextureFormat,
+    /// Format of the views we render to; pipelines should target this
+    view_format: TextureFormat,
+    size: (u32, u32),
+    /// Present modes the surface supports on this adapter
+    present_modes: Vec<PresentMode>,
+    present: PresentConfig,
+}
+
+/// An acquired surface texture, ready to be rendered to.
+pub struct SurfaceFrame {
+    pub texture: SurfaceTexture,
+    pub view: TextureView,
+}
+
+impl SurfaceFrame {
+    pub fn present(self) {
+        self.texture.present();
+    }
+}
+
+impl SurfaceManager {
+    /// Creates the manager and configures the surface for the first time.
+    pub fn new(
+        surface: Surface<'static>,
+        adapter: &Adapter,
+        device: &Device,
+        size: (u32, u32),
+        srgb: SrgbPolicy,
+    ) -> Self {
+        let caps = surface.get_capabilities(adapter);
+        // Prefer a format that already matches the policy; otherwise take the preferred
+        // one and reinterpret it through `view_formats`.
+        let surface_format = caps
+            .formats
+            .iter()
+            .copied()
+            .find(|&x| srgb.apply(x) == x)
+            .unwrap_or(caps.formats[0]);
+
+        let manager = Self {
+            surface,
+            device: device.clone(),
+            surface_format,
+            view_format: srgb.apply(surface_format),
+            size,
+            present_modes: caps.present_modes,
+            present: PresentConfig::default(),
+        };
+        manager.configure();
+        manager
+    }
+
+    /// The format render pipelines drawing to this surface should use.
+    pub fn format(&self) -> TextureFormat {
+        self.view_format
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn present_config(&self) -> PresentConfig {
+        self.present
+    }
+
+    /// Checks `config` against the surface capabilities and reconfigures with it.
+    pub fn set_present_config(&mut self, config: PresentConfig) -> anyhow::Result<()> {
+        let auto = matches!(
+            config.present_mode,
+            PresentMode::AutoVsync | PresentMode::AutoNoVsync
+        );
+        if !auto && !self.present_modes.contains(&config.present_mode) {
+            bail!(
+                "Present mode {:?} is not supported by this surface; supported: {:?}",
+                config.present_mode,
+                self.present_modes
+            );
+        }
+        if config.desired_maximum_frame_latency == 0 {
+            bail!("Frame latency must be at least 1");
+        }
+        self.present = config;
+        self.configure();
+        Ok(())
+    }
+
+    pub fn configure(&self) {
+        info!(
+            "Configure the surface; size: {:?}, present: {:?}",
+            self.size, self.present
+        );
+        self.surface.configure(
+            &self.device,
+            &SurfaceConfiguration {
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                format: self.surface_format,
+                // Request compatibility with the view format we're going to create later.
+                view_formats: vec![self.view_format],
+                alpha_mode: CompositeAlphaMode::Auto,
+                width: self.size.0,
+                height: self.size.1,
+                desired_maximum_frame_latency: self.present.desired_maximum_frame_latency,
+                present_mode: self.present.present_mode,
+            },
+        );
+    }
+
+    pub fn resize(&mut self, size: (u32, u32)) {
+        if size.0 == 0 || size.1 == 0 {
+            // minimized; a zero-sized surface can't be configured
+            return;
+        }
+        self.size = size;
+        self.configure();
+    }
+
+    /// Acquires the next frame. Returns `None` if this frame should be skipped.
+    pub fn acquire(&self) -> Option<SurfaceFrame> {
+        let texture = match self.surface.get_current_texture() {
+            CurrentSurfaceTexture::Success(texture) => texture,
+            CurrentSurfaceTexture::Suboptimal(texture) => {
+                info!("Suboptimal surface texture, reconfiguring...");
+                self.configure();
+                texture
+            }
+            CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
+                return None;
+            }
+            CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
+                self.configure();
+                return None;
+            }
+            CurrentSurfaceTexture::Validation => {
+                error!("Validation error in get_current_texture");
+                return None;
+            }
+        };
+        let view = texture.texture.create_view(&TextureViewDescriptor {
+            format: Some(self.view_format),
+            ..Default::default()
+        });
+        Some(SurfaceFrame { texture, view })
+    }
+}
@@ -1,9 +1,10 @@
-use crate::{ColorExt, WgpuStateInitInfo};
+use crate::surface::{SrgbPolicy, SurfaceManager};
+use crate::{AdapterSelector, ColorExt, WgpuStateInitInfo};
 use bytemuck::checked::cast_slice;
-use log::{error, info};
+use log::info;
 use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferDescriptor, BufferUsages,
-    Color, ColorTargetState, CurrentSurfaceTexture, Device, FragmentState, Queue, RenderPipeline,
+    Color, ColorTargetState, Device, FragmentState, Queue, RenderPipeline,
     RenderPipelineDescriptor, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState,
     include_wgsl,
 };
@@ -11,9 +12,7 @@ use wgpu::{
 pub struct State {
     device: wgpu::Device,
     queue: wgpu::Queue,
-    size: (u32, u32),
-    surface: wgpu::Surface<'static>,
-    surface_format: wgpu::TextureFormat,
+    surface: SurfaceManager,
     pipeline: RenderPipeline,
     vertex_buffer: Buffer,
     uniform_buffer: Buffer,
@@ -38,21 +37,19 @@ static VERTICES_DATA: [f32; 15] = {
 };
 
 impl State {
-    pub async fn new(info: WgpuStateInitInfo) -> State {
+    pub async fn new(info: WgpuStateInitInfo) -> anyhow::Result<State> {
         let instance = info.instance;
-        let surface = info.surface;
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
-            .await
-            .unwrap();
+        let adapter = AdapterSelector::from_env()
+            .select(&instance, Some(&info.surface))
+            .await?;
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor::default())
-            .await
-            .unwrap();
+            .await?;
 
-        let cap = surface.get_capabilities(&adapter);
-
-        let surface_format = cap.formats[0];
+        // Without an sRGB view the image we will be working with might not be "gamma correct".
+        let mut surface =
+            SurfaceManager::new(info.surface, &adapter, &device, info.size, SrgbPolicy::Srgb);
+        surface.set_present_config(info.present)?;
 
         let shader_module =
             device.create_shader_module(include_wgsl!("shaders/triangle-rotation.wgsl"));
@@ -89,7 +86,7 @@ impl State {
                 entry_point: None,
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: surface_format.add_srgb_suffix(),
+                    format: surface.format(),
                     blend: None,
                     write_mask: Default::default(),
                 })],
@@ -110,21 +107,14 @@ impl State {
             usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
             mapped_at_creation: false,
         });
-        let state = State {
+        Ok(State {
             uniform_buffer: buffer,
             device,
             queue,
-            size: info.size,
             surface,
-            surface_format,
             pipeline,
             vertex_buffer,
-        };
-
-        // Configure surface for the first time
-        state.configure_surface();
-
-        state
+        })
     }
 
     fn create_vertex_buffer(device: &Device, queue: &Queue, data: &[f32]) -> Buffer {
@@ -143,62 +133,16 @@ impl State {
             .write_buffer(&self.uniform_buffer, 0, cast_slice(&[value]));
     }
 
-    pub fn configure_surface(&self) {
-        info!("Configure the surface; size: {:?}", self.size);
-
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: self.surface_format,
-            // Request compatibility with the sRGB-format texture view we‘re going to create later.
-            view_formats: vec![self.surface_format.add_srgb_suffix()],
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            width: self.size.0,
-            height: self.size.1,
-            desired_maximum_frame_latency: 2,
-            present_mode: wgpu::PresentMode::AutoVsync,
-        };
-        self.surface.configure(&self.device, &surface_config);
-    }
-
     pub fn resize(&mut self, new_size: (u32, u32)) {
-        self.size = new_size;
-
-        // reconfigure the surface
-        self.configure_surface();
+        self.surface.resize(new_size);
     }
 
     pub fn render(&self, redraw_callback: impl FnOnce()) {
-        info!("Render; size: {:?}", self.size);
-
-        // Create texture view
-        let surface_texture = match self.surface.get_current_texture() {
-            CurrentSurfaceTexture::Success(texture) => texture,
-            CurrentSurfaceTexture::Suboptimal(texture) => {
-                info!("Suboptimal surface texture, reconfiguring...");
-                self.configure_surface();
-                texture
-            }
-            CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
-                return;
-            }
-            CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
-                self.configure_surface();
-                return;
-            }
-            CurrentSurfaceTexture::Validation => {
-                error!("Validation error in get_current_texture");
-                return;
-            }
-        };
+        info!("Render; size: {:?}", self.surface.size());
 
-        let texture_view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor {
-                // Without add_srgb_suffix() the image we will be working with
-                // might not be "gamma correct".
-                format: Some(self.surface_format.add_srgb_suffix()),
-                ..Default::default()
-            });
+        let Some(frame) = self.surface.acquire() else {
+            return;
+        };
 
         // Renders a gray screen
         let mut encoder = self.device.create_command_encoder(&Default::default());
@@ -206,7 +150,7 @@ impl State {
         let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
+                view: &frame.view,
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
@@ -239,6 +183,6 @@ impl State {
         // Submit the command in the queue to execute
         redraw_callback();
         self.queue.submit([encoder.finish()]);
-        surface_texture.present();
+        frame.present();
     }
 }
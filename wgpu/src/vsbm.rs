@@ -1,7 +1,15 @@
-use crate::{WgpuStateInitInfo, default};
+use crate::surface::{PresentConfig, SrgbPolicy, SurfaceManager};
+use crate::{
+    AdapterSelector, GpuTimer, RenderTarget, WgpuStateInitInfo, create_offscreen_texture, default,
+    read_texture,
+};
+use anyhow::anyhow;
 use bytemuck::{Pod, Zeroable};
+use std::f32::consts::FRAC_PI_2;
 use std::iter;
-use wgpu::{CurrentSurfaceTexture, PipelineCompilationOptions, TextureFormat};
+use std::time::Duration;
+use wgpu::wgt::PollType;
+use wgpu::{AdapterInfo, Features, Instance, PipelineCompilationOptions, TextureFormat};
 
 // --- Uniform 数据结构 (必须符合 WGSL 的 16 字节对齐) ---
 #[repr(C)]
@@ -17,66 +25,168 @@ pub struct Uniforms {
     padding4: f32,
     screen_size: [f32; 2],
     len: f32,
-    padding5: f32,
+    power: f32,
+    bailout: f32,
+    step_size: f32,
+    max_steps: u32,
+    refine_iterations: u32,
 }
 
 pub struct State {
-    surface: wgpu::Surface<'static>,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
     pub size: (u32, u32),
     render_pipeline: wgpu::RenderPipeline,
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
-    elapsed: f32,
+    camera: Camera,
+    pub auto_rotate: bool,
+    pub fractal: FractalParams,
     texture_format: wgpu::TextureFormat,
+    adapter_info: AdapterInfo,
+    gpu_timer: Option<GpuTimer>,
 }
 
+/// An orbit camera looking at the origin.
+#[derive(Debug, Copy, Clone)]
+pub struct Camera {
+    /// Rotation around the Y axis, in radians
+    pub yaw: f32,
+    /// Elevation above the XZ plane, in radians
+    pub pitch: f32,
+    /// Distance to the origin
+    pub distance: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 2.8,
+            pitch: 0.4,
+            distance: 1.6,
+        }
+    }
+}
+
+impl Camera {
+    const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+    const DISTANCE_RANGE: (f32, f32) = (0.5, 4.0);
+
+    pub fn rotate(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        self.yaw += delta_yaw;
+        self.pitch = (self.pitch + delta_pitch).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+
+    /// Multiplies the distance by `factor`. Values below 1 move the camera closer.
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance =
+            (self.distance * factor).clamp(Self::DISTANCE_RANGE.0, Self::DISTANCE_RANGE.1);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct Config {
+    /// Baked into the pipeline; can't be changed after [`State`] creation.
     pub kernel_iterations: u32,
+    pub fractal: FractalParams,
+    /// Time each render pass with timestamp queries, if the adapter supports it.
+    pub gpu_timing: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             kernel_iterations: 5,
+            fractal: default!(),
+            gpu_timing: false,
+        }
+    }
+}
+
+/// Fractal and ray-marching parameters. Passed as uniforms, so they can be changed
+/// at runtime through [`State::fractal`].
+#[derive(Debug, Copy, Clone)]
+pub struct FractalParams {
+    /// Power of the Mandelbulb formula
+    pub power: f32,
+    pub bailout: f32,
+    /// Ray-march step size, relative to the camera distance
+    pub step_size: f32,
+    pub max_steps: u32,
+    /// Bisection/golden-section iterations used to refine a hit
+    pub refine_iterations: u32,
+}
+
+impl Default for FractalParams {
+    fn default() -> Self {
+        Self {
+            power: 8.0,
+            bailout: 6.0,
+            step_size: 0.002,
+            max_steps: 1000,
+            refine_iterations: 8,
         }
     }
 }
 
 impl State {
-    pub fn configure_surface(&self) {
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: self.texture_format,
-            view_formats: vec![self.texture_format],
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            width: self.size.0,
-            height: self.size.1,
-            desired_maximum_frame_latency: 2,
-            present_mode: wgpu::PresentMode::AutoVsync,
-        };
-        self.surface.configure(&self.device, &surface_config);
+    pub async fn new(info: WgpuStateInitInfo, config: Config) -> anyhow::Result<Self> {
+        Self::create(
+            info.instance,
+            Some((info.surface, info.present)),
+            info.size,
+            config,
+        )
+        .await
+    }
+
+    /// Creates a [`State`] that renders to an offscreen texture instead of a window.
+    ///
+    /// Rendered frames can be fetched with [`State::read_pixels`].
+    pub async fn new_headless(
+        instance: Instance,
+        size: (u32, u32),
+        config: Config,
+    ) -> anyhow::Result<Self> {
+        Self::create(instance, None, size, config).await
     }
 
-    pub async fn new(info: WgpuStateInitInfo, config: Config) -> Self {
-        let instance = info.instance;
-        let surface = info.surface;
-        let adapter = instance.request_adapter(&default!()).await.unwrap();
+    async fn create(
+        instance: Instance,
+        surface: Option<(wgpu::Surface<'static>, PresentConfig)>,
+        size: (u32, u32),
+        config: Config,
+    ) -> anyhow::Result<Self> {
+        let adapter = AdapterSelector::from_env()
+            .select(&instance, surface.as_ref().map(|(x, _)| x))
+            .await?;
 
+        let gpu_timing =
+            config.gpu_timing && adapter.features().contains(Features::TIMESTAMP_QUERY);
         let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default())
-            .await
-            .unwrap();
-
-        let surface_caps = surface.get_capabilities(&adapter);
+            .request_device(&wgpu::DeviceDescriptor {
+                required_features: if gpu_timing {
+                    Features::TIMESTAMP_QUERY
+                } else {
+                    Features::empty()
+                },
+                ..Default::default()
+            })
+            .await?;
+        let gpu_timer = gpu_timing.then(|| GpuTimer::new(&device));
 
-        // Do not use srgb suffix. This makes wgpu think all colors we give are already in a
-        // non-linear sRGB space and do not do an automatic gamma correction.
-        let mut texture_format = TextureFormat::Bgra8Unorm;
-        if !surface_caps.formats.iter().any(|x| x == &texture_format) {
-            texture_format = surface_caps.formats[0].remove_srgb_suffix();
-        }
+        let target = match surface {
+            Some((surface, present)) => {
+                let mut surface =
+                    SurfaceManager::new(surface, &adapter, &device, size, SrgbPolicy::NoSrgb);
+                surface.set_present_config(present)?;
+                RenderTarget::Surface(surface)
+            }
+            // RGBA is the natural layout for reading pixels back.
+            None => RenderTarget::offscreen(&device, size, TextureFormat::Rgba8Unorm),
+        };
+        let texture_format = target.format();
 
         // --- 核心 WGSL 着色器 ---
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -151,33 +261,96 @@ impl State {
             cache: None,
         });
 
-        let state = Self {
-            surface,
+        Ok(Self {
+            target,
             device,
             queue,
-            size: info.size,
+            size,
             render_pipeline,
             uniform_buffer,
             uniform_bind_group,
-            elapsed: 0f32,
+            camera: default!(),
+            auto_rotate: true,
+            fractal: config.fractal,
+            adapter_info: adapter.get_info(),
+            gpu_timer,
             texture_format,
-        };
-        state.configure_surface();
-        state
+        })
     }
 
     pub fn resize(&mut self, new_size: (u32, u32)) {
+        if new_size.0 == 0 || new_size.1 == 0 {
+            // minimized; a zero-sized surface can't be configured
+            return;
+        }
         self.size = new_size;
 
-        // reconfigure the surface
-        self.configure_surface();
+        match &mut self.target {
+            RenderTarget::Surface(surface) => surface.resize(new_size),
+            RenderTarget::Texture(texture) => {
+                *texture = create_offscreen_texture(&self.device, new_size, self.texture_format);
+            }
+        }
+    }
+
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Whether render passes are timed on the GPU. See [`Config::gpu_timing`].
+    pub fn has_gpu_timer(&self) -> bool {
+        self.gpu_timer.is_some()
+    }
+
+    /// Returns the GPU duration of the last rendered frame, or `None` if GPU timing is off.
+    pub async fn read_gpu_time(&self) -> anyhow::Result<Option<Duration>> {
+        match &self.gpu_timer {
+            Some(timer) => Ok(Some(timer.read(&self.device, &self.queue).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Blocks until all submitted work has finished.
+    pub fn wait_idle(&self) -> anyhow::Result<()> {
+        self.device.poll(PollType::Wait {
+            submission_index: None,
+            timeout: None,
+        })?;
+        Ok(())
+    }
+
+    pub fn texture_format(&self) -> TextureFormat {
+        self.texture_format
+    }
+
+    /// Reads back the last rendered frame of a headless [`State`].
+    ///
+    /// Returns tightly packed pixels in [`State::texture_format`].
+    pub async fn read_pixels(&self) -> anyhow::Result<Vec<u8>> {
+        let RenderTarget::Texture(texture) = &self.target else {
+            return Err(anyhow!(
+                "Pixels can only be read from an offscreen render target"
+            ));
+        };
+        read_texture(&self.device, &self.queue, texture).await
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
     }
 
     pub fn update(&mut self) {
-        self.elapsed += 0.012;
-        let ang1 = 2.8 + self.elapsed * 0.5; // 自动旋转
-        let ang2: f32 = 0.4;
-        let len = 1.6;
+        if self.auto_rotate {
+            // 自动旋转
+            self.camera.yaw += 0.006;
+        }
+        let ang1 = self.camera.yaw;
+        let ang2 = self.camera.pitch;
+        let len = self.camera.distance;
 
         let origin = [
             len * ang1.cos() * ang2.cos(),
@@ -196,12 +369,12 @@ impl State {
             -ang1.sin() * ang2.cos(),
         ];
 
-        // let cx = self.size.0 as f32;
-        // let cy = self.size.1 as f32;
-        // let sx = (cx.min(cy) / cx) * (cx / cx.max(cy));
-        // let sy = (cy.min(cx) / cy) * (cy / cx.max(cy));
+        // Keep the shorter side at the original field of view and extend the longer one,
+        // so the fractal is not stretched on non-square targets.
+        let cx = self.size.0 as f32;
+        let cy = self.size.1 as f32;
+        let screen_size = [cx / cx.min(cy), cy / cx.min(cy)];
 
-        // 因为使用了 1:1 的 Viewport，这里 screen_size 直接给 1.0 即可
         let uniforms = Uniforms {
             origin,
             padding1: 0.0,
@@ -211,9 +384,13 @@ impl State {
             padding3: 0.0,
             forward,
             padding4: 0.0,
-            screen_size: [1.0, 1.0],
+            screen_size,
             len,
-            padding5: 0.0,
+            power: self.fractal.power,
+            bailout: self.fractal.bailout,
+            step_size: self.fractal.step_size,
+            max_steps: self.fractal.max_steps,
+            refine_iterations: self.fractal.refine_iterations,
         };
 
         self.queue
@@ -221,33 +398,20 @@ impl State {
     }
 
     pub fn render(&self, before_submit_callback: impl FnOnce()) {
-        let surface_texture = match self.surface.get_current_texture() {
-            CurrentSurfaceTexture::Success(texture) => texture,
-            CurrentSurfaceTexture::Suboptimal(texture) => {
-                log::info!("Suboptimal surface texture, reconfiguring...");
-                self.configure_surface();
-                texture
-            }
-            CurrentSurfaceTexture::Timeout | CurrentSurfaceTexture::Occluded => {
-                return;
-            }
-            CurrentSurfaceTexture::Outdated | CurrentSurfaceTexture::Lost => {
-                self.configure_surface();
-                return;
-            }
-            CurrentSurfaceTexture::Validation => {
-                log::error!("Validation error in get_current_texture");
-                return;
+        let (frame, texture_view) = match &self.target {
+            RenderTarget::Surface(surface) => {
+                let Some(frame) = surface.acquire() else {
+                    return;
+                };
+                let view = frame.view.clone();
+                (Some(frame), view)
             }
+            RenderTarget::Texture(texture) => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
         };
 
-        let texture_view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor {
-                format: Some(self.texture_format),
-                ..Default::default()
-            });
-
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -273,26 +437,22 @@ impl State {
                 })],
                 depth_stencil_attachment: None,
                 occlusion_query_set: None,
-                timestamp_writes: None,
+                timestamp_writes: self.gpu_timer.as_ref().map(|x| x.timestamp_writes()),
                 multiview_mask: None,
             });
 
-            // --- 核心逻辑：设置居中的 1:1 正方形视口 ---
-            // let win_w = self.size.0 as f32;
-            // let win_h = self.size.1 as f32;
-            // let side = win_w.min(win_h); // 取短边
-            // let x_offset = (win_w - side) / 2.0;
-            // let y_offset = (win_h - side) / 2.0;
-            //
-            // render_pass.set_viewport(x_offset, y_offset, side, side, 0.0, 1.0);
-
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
+        if let Some(timer) = &self.gpu_timer {
+            timer.resolve(&mut encoder);
+        }
 
         before_submit_callback();
         self.queue.submit(iter::once(encoder.finish()));
-        surface_texture.present();
+        if let Some(frame) = frame {
+            frame.present();
+        }
     }
 }